@@ -0,0 +1,286 @@
+use std::cell::RefCell;
+
+use gdk_pixbuf::Pixbuf;
+use gtk::cairo;
+use gtk::gdk::prelude::GdkCairoContextExt;
+
+use crate::editor::BlurMode;
+
+const MAX_REDACTIONS: usize = 64;
+const MAX_COMPOSITES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RedactionKey {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub mode: BlurMode,
+    pub strength: i32,
+}
+
+// Every entry keeps a strong reference to the pixbuf it was derived from, so identity
+// comparisons can't be fooled by a freed pixbuf's address being reused.
+struct Redaction {
+    source: Pixbuf,
+    key: RedactionKey,
+    surface: cairo::ImageSurface,
+}
+
+struct Composite {
+    background: Pixbuf,
+    key: String,
+    pixbuf: Pixbuf,
+}
+
+struct Scaled {
+    scale: f64,
+    surface: cairo::ImageSurface,
+}
+
+#[derive(Default)]
+struct Entries {
+    background: Option<(Pixbuf, cairo::ImageSurface)>,
+    scaled: Option<Scaled>,
+    redactions: Vec<Redaction>,
+    composites: Vec<Composite>,
+}
+
+#[derive(Default)]
+pub struct RenderCache {
+    entries: RefCell<Entries>,
+}
+
+impl RenderCache {
+    // Returns the background as a cairo surface along with the factor it was scaled by.
+    // Zoomed-out views get a downscaled copy so each frame doesn't resample the full image.
+    pub fn background(
+        &self,
+        background: &Pixbuf,
+        scale: f64,
+    ) -> Option<(cairo::ImageSurface, f64)> {
+        let full = {
+            let mut entries = self.entries.borrow_mut();
+            match entries.background.as_ref() {
+                Some((cached, surface)) if cached == background => surface.clone(),
+                _ => {
+                    *entries = Entries::default();
+                    let surface = surface_from_pixbuf(background)?;
+                    entries.background = Some((background.clone(), surface.clone()));
+                    surface
+                }
+            }
+        };
+        if scale >= 1.0 || scale <= 0.0 {
+            return Some((full, 1.0));
+        }
+
+        let mut entries = self.entries.borrow_mut();
+        if let Some(scaled) = entries.scaled.as_ref()
+            && scaled.scale == scale
+        {
+            return Some((scaled.surface.clone(), scale));
+        }
+        let width = ((background.width() as f64 * scale).ceil() as i32).max(1);
+        let height = ((background.height() as f64 * scale).ceil() as i32).max(1);
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height).ok()?;
+        let ctx = cairo::Context::new(&surface).ok()?;
+        ctx.scale(scale, scale);
+        ctx.set_source_surface(&full, 0.0, 0.0).ok()?;
+        ctx.source().set_filter(cairo::Filter::Good);
+        ctx.paint().ok()?;
+        drop(ctx);
+        entries.scaled = Some(Scaled {
+            scale,
+            surface: surface.clone(),
+        });
+        Some((surface, scale))
+    }
+
+    pub fn redaction(
+        &self,
+        source: &Pixbuf,
+        key: RedactionKey,
+        build: impl FnOnce() -> Option<Pixbuf>,
+    ) -> Option<cairo::ImageSurface> {
+        if let Some(entry) = self
+            .entries
+            .borrow()
+            .redactions
+            .iter()
+            .find(|entry| entry.key == key && entry.source == *source)
+        {
+            return Some(entry.surface.clone());
+        }
+
+        let surface = surface_from_pixbuf(&build()?)?;
+        let mut entries = self.entries.borrow_mut();
+        if entries.redactions.len() >= MAX_REDACTIONS {
+            entries.redactions.remove(0);
+        }
+        entries.redactions.push(Redaction {
+            source: source.clone(),
+            key,
+            surface: surface.clone(),
+        });
+        Some(surface)
+    }
+
+    pub fn composite(
+        &self,
+        background: &Pixbuf,
+        key: String,
+        build: impl FnOnce() -> Option<Pixbuf>,
+    ) -> Option<Pixbuf> {
+        if let Some(entry) = self
+            .entries
+            .borrow()
+            .composites
+            .iter()
+            .find(|entry| entry.key == key && entry.background == *background)
+        {
+            return Some(entry.pixbuf.clone());
+        }
+
+        let pixbuf = build()?;
+        let mut entries = self.entries.borrow_mut();
+        if entries.composites.len() >= MAX_COMPOSITES {
+            let evicted = entries.composites.remove(0);
+            entries
+                .redactions
+                .retain(|entry| entry.source != evicted.pixbuf);
+        }
+        entries.composites.push(Composite {
+            background: background.clone(),
+            key,
+            pixbuf: pixbuf.clone(),
+        });
+        Some(pixbuf)
+    }
+}
+
+pub fn surface_from_pixbuf(pixbuf: &Pixbuf) -> Option<cairo::ImageSurface> {
+    let surface =
+        cairo::ImageSurface::create(cairo::Format::ARgb32, pixbuf.width(), pixbuf.height()).ok()?;
+    let ctx = cairo::Context::new(&surface).ok()?;
+    ctx.set_source_pixbuf(pixbuf, 0.0, 0.0);
+    ctx.paint().ok()?;
+    drop(ctx);
+    Some(surface)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use gdk_pixbuf::Colorspace;
+
+    use super::*;
+
+    fn pixbuf() -> Pixbuf {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, true, 8, 8, 8).unwrap();
+        pixbuf.fill(0x336699ff);
+        pixbuf
+    }
+
+    fn key(x: i32, mode: BlurMode, strength: i32) -> RedactionKey {
+        RedactionKey {
+            x,
+            y: 0,
+            width: 4,
+            height: 4,
+            mode,
+            strength,
+        }
+    }
+
+    #[test]
+    fn background_is_dropped_when_the_pixbuf_changes() {
+        let cache = RenderCache::default();
+        let (first, second) = (pixbuf(), pixbuf());
+        cache.background(&first, 1.0).unwrap();
+        cache.redaction(&first, key(0, BlurMode::Pixelate, 4), || Some(pixbuf()));
+
+        cache.background(&second, 1.0).unwrap();
+        let entries = cache.entries.borrow();
+        assert!(
+            entries
+                .background
+                .as_ref()
+                .is_some_and(|(cached, _)| *cached == second)
+        );
+        assert!(entries.redactions.is_empty());
+    }
+
+    #[test]
+    fn redactions_are_reused_only_for_the_same_key() {
+        let cache = RenderCache::default();
+        let source = pixbuf();
+        let builds = Cell::new(0);
+        let build = || {
+            builds.set(builds.get() + 1);
+            Some(pixbuf())
+        };
+        cache.redaction(&source, key(0, BlurMode::Pixelate, 4), build);
+        cache.redaction(&source, key(0, BlurMode::Pixelate, 4), build);
+        assert_eq!(builds.get(), 1);
+
+        cache.redaction(&source, key(1, BlurMode::Pixelate, 4), build);
+        cache.redaction(&source, key(0, BlurMode::Solid, 4), build);
+        cache.redaction(&source, key(0, BlurMode::Pixelate, 5), build);
+        cache.redaction(&pixbuf(), key(0, BlurMode::Pixelate, 4), build);
+        assert_eq!(builds.get(), 5);
+    }
+
+    #[test]
+    fn redactions_are_capped() {
+        let cache = RenderCache::default();
+        let source = pixbuf();
+        let builds = Cell::new(0);
+        let build = || {
+            builds.set(builds.get() + 1);
+            Some(pixbuf())
+        };
+        for x in 0..=MAX_REDACTIONS as i32 {
+            cache.redaction(&source, key(x, BlurMode::Pixelate, 4), build);
+        }
+        assert_eq!(cache.entries.borrow().redactions.len(), MAX_REDACTIONS);
+
+        // The oldest entry was evicted, the newest is still there.
+        cache.redaction(
+            &source,
+            key(MAX_REDACTIONS as i32, BlurMode::Pixelate, 4),
+            build,
+        );
+        assert_eq!(builds.get(), MAX_REDACTIONS + 1);
+        cache.redaction(&source, key(0, BlurMode::Pixelate, 4), build);
+        assert_eq!(builds.get(), MAX_REDACTIONS + 2);
+    }
+
+    #[test]
+    fn composites_are_capped_and_take_their_redactions_with_them() {
+        let cache = RenderCache::default();
+        let background = pixbuf();
+        let builds = Cell::new(0);
+        let build = || {
+            builds.set(builds.get() + 1);
+            Some(pixbuf())
+        };
+        let first = cache
+            .composite(&background, "0".to_string(), build)
+            .unwrap();
+        cache.redaction(&first, key(0, BlurMode::Pixelate, 4), || Some(pixbuf()));
+        for index in 1..=MAX_COMPOSITES {
+            cache.composite(&background, index.to_string(), build);
+        }
+        let entries = cache.entries.borrow();
+        assert_eq!(entries.composites.len(), MAX_COMPOSITES);
+        assert!(entries.redactions.is_empty());
+        drop(entries);
+
+        cache.composite(&background, MAX_COMPOSITES.to_string(), build);
+        assert_eq!(builds.get(), MAX_COMPOSITES + 1);
+        cache.composite(&background, "0".to_string(), build);
+        assert_eq!(builds.get(), MAX_COMPOSITES + 2);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use adw::prelude::*;
+use gtk::gio;
+use gtk::glib;
+
+use crate::ui::{CaptureOptions, EditorWindow};
+
+pub fn add_options(app: &adw::Application) {
+    app.add_main_option(
+        "capture",
+        glib::Char::from(b'c'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Capture a screenshot on startup",
+        None,
+    );
+    app.add_main_option(
+        "delay",
+        glib::Char::from(b'd'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Double,
+        "Capture delay in seconds",
+        Some("SECONDS"),
+    );
+    app.add_main_option(
+        "no-interactive",
+        glib::Char::from(b'n'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Capture without the portal selection dialog",
+        None,
+    );
+    app.add_main_option(
+        "output",
+        glib::Char::from(b'o'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        "Save the capture to FILE and exit",
+        Some("FILE"),
+    );
+    app.add_main_option(
+        "clipboard",
+        glib::Char::from(b'b'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Copy the capture to the clipboard",
+        None,
+    );
+}
+
+pub fn handle_command_line(
+    app: &adw::Application,
+    editor: &EditorWindow,
+    command_line: &gio::ApplicationCommandLine,
+) -> glib::ExitCode {
+    let options = command_line.options_dict();
+    let capture = options.contains("capture");
+    let delay = options.lookup::<f64>("delay").ok().flatten();
+    let interactive = options.contains("no-interactive").then_some(false);
+    let output = options
+        .lookup::<PathBuf>("output")
+        .ok()
+        .flatten()
+        .map(|path| match command_line.cwd() {
+            Some(cwd) if path.is_relative() => cwd.join(path),
+            _ => path,
+        });
+    let clipboard = options.contains("clipboard");
+    let files: Vec<gio::File> = command_line
+        .arguments()
+        .iter()
+        .skip(1)
+        .map(|arg| command_line.create_file_for_arg(arg))
+        .collect();
+
+    if !capture && (output.is_some() || clipboard) {
+        command_line.printerr_literal("--output and --clipboard require --capture\n");
+        return glib::ExitCode::FAILURE;
+    }
+
+    editor.set_capture_defaults(delay, interactive);
+    if capture {
+        let quit_after = output.is_some() && !clipboard && !editor.window.is_visible();
+        editor.capture(CaptureOptions {
+            delay: delay.unwrap_or_else(|| editor.capture_delay()),
+            interactive: interactive.unwrap_or_else(|| editor.interactive()),
+            output,
+            clipboard,
+            quit_after,
+            command_line: Some(command_line.clone()),
+            hold: Some(app.hold()),
+        });
+    } else if !files.is_empty() {
+        app.open(&files, "");
+    } else {
+        app.activate();
+    }
+    glib::ExitCode::SUCCESS
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gtk::glib;
+use serde::de::DeserializeOwned;
+
+pub fn dir() -> PathBuf {
+    glib::user_config_dir().join("greatshot")
+}
+
+// A missing file is Ok(None) and means defaults. One that can't be read or parsed is an
+// error, and the caller must not save over it, so the user's file survives until they fix it.
+pub fn load_toml<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let Some(text) = read(path)? else {
+        return Ok(None);
+    };
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err.message()))
+}
+
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let Some(text) = read(path)? else {
+        return Ok(None);
+    };
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|err| format!("Failed to parse {}: {err}", path.display()))
+}
+
+fn read(path: &Path) -> Result<Option<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("Failed to read {}: {err}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("greatshot-config-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn missing_files_load_as_none() {
+        let path = temp_path("missing.toml");
+        let loaded: Option<BTreeMap<String, u32>> = load_toml(&path).unwrap();
+        assert!(loaded.is_none());
+        let loaded: Option<BTreeMap<String, u32>> = load_json(&path).unwrap();
+        assert!(loaded.is_none());
+    }
+
+    #[test]
+    fn parses_toml_and_json() {
+        let path = temp_path("values.toml");
+        fs::write(&path, "answer = 42").unwrap();
+        let loaded: Result<Option<BTreeMap<String, u32>>, String> = load_toml(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap().unwrap()["answer"], 42);
+
+        let path = temp_path("values.json");
+        fs::write(&path, r#"{ "answer": 42 }"#).unwrap();
+        let loaded: Result<Option<BTreeMap<String, u32>>, String> = load_json(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap().unwrap()["answer"], 42);
+    }
+
+    #[test]
+    fn reports_unparsable_and_unreadable_files() {
+        let path = temp_path("broken.toml");
+        fs::write(&path, "answer = ").unwrap();
+        let loaded: Result<Option<BTreeMap<String, u32>>, String> = load_toml(&path);
+        let _ = fs::remove_file(&path);
+        let err = loaded.err().unwrap();
+        assert!(err.starts_with("Failed to parse"), "{err}");
+
+        let path = temp_path("directory.json");
+        fs::create_dir_all(&path).unwrap();
+        let loaded: Result<Option<BTreeMap<String, u32>>, String> = load_json(&path);
+        let _ = fs::remove_dir(&path);
+        let err = loaded.err().unwrap();
+        assert!(err.starts_with("Failed to read"), "{err}");
+    }
+}
@@ -1,15 +1,27 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
+
 use gtk::cairo;
 use gtk::gdk;
 use gtk::gdk::prelude::GdkCairoContextExt;
+use gtk::glib;
+use gtk::glib::translate::IntoGlib;
+use gtk::pango;
+use gtk::pango::prelude::FontMapExt;
 use gdk_pixbuf::Pixbuf;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+use crate::cache::{self, RedactionKey, RenderCache};
+use crate::history::{self, Command, History};
+use crate::redact;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Rect {
     pub x1: f64,
     pub y1: f64,
@@ -27,21 +39,37 @@ impl Rect {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Annotation {
     Pen {
         points: Vec<Point>,
+        #[serde(with = "rgba")]
         color: gdk::RGBA,
         width: f64,
+        #[serde(default)]
+        highlighter: bool,
     },
     Rect {
         rect: Rect,
+        #[serde(with = "rgba")]
+        color: gdk::RGBA,
+        width: f64,
+        #[serde(default, with = "rgba::option", skip_serializing_if = "Option::is_none")]
+        fill: Option<gdk::RGBA>,
+    },
+    Ellipse {
+        rect: Rect,
+        #[serde(with = "rgba")]
         color: gdk::RGBA,
         width: f64,
+        #[serde(default, with = "rgba::option", skip_serializing_if = "Option::is_none")]
+        fill: Option<gdk::RGBA>,
     },
     Line {
         start: Point,
         end: Point,
+        #[serde(with = "rgba")]
         color: gdk::RGBA,
         width: f64,
         arrow: bool,
@@ -49,35 +77,260 @@ pub enum Annotation {
     Text {
         pos: Point,
         text: String,
+        #[serde(with = "rgba")]
         color: gdk::RGBA,
         size: f64,
+        #[serde(default)]
+        font: TextFont,
+    },
+    Callout {
+        pos: Point,
+        tip: Point,
+        text: String,
+        #[serde(with = "rgba")]
+        color: gdk::RGBA,
+        size: f64,
+        #[serde(default)]
+        font: TextFont,
+    },
+    Step {
+        center: Point,
+        radius: f64,
+        #[serde(with = "rgba")]
+        color: gdk::RGBA,
     },
     Blur {
         rect: Rect,
-        pixel_size: i32,
+        #[serde(default)]
+        mode: BlurMode,
+        #[serde(alias = "pixel_size")]
+        strength: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        region: Option<BlurRegion>,
+        #[serde(default)]
+        include_below: bool,
+    },
+    Magnify {
+        source: Rect,
+        rect: Rect,
+        #[serde(with = "rgba")]
+        color: gdk::RGBA,
+        width: f64,
+        #[serde(default)]
+        connectors: bool,
+    },
+    Spotlight {
+        holes: Vec<SpotlightHole>,
+        opacity: f64,
     },
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpotlightHole {
+    pub rect: Rect,
+    #[serde(default)]
+    pub shape: SpotlightShape,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpotlightShape {
+    #[default]
+    Rect,
+    Ellipse,
+}
+
+// Freeform redaction shapes; the annotation's `rect` is kept as their bounding box.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlurRegion {
+    Brush { points: Vec<Point>, width: f64 },
+    Polygon { points: Vec<Point> },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlurShape {
+    #[default]
+    Rect,
+    Brush,
+    Lasso,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlurMode {
+    #[default]
+    Pixelate,
+    Gaussian,
+    Solid,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FontStyle {
+    #[default]
+    Normal,
+    Italic,
+    Oblique,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextFont {
+    pub family: String,
+    pub weight: i32,
+    pub style: FontStyle,
+}
+
+impl Default for TextFont {
+    fn default() -> Self {
+        Self {
+            family: "Sans".to_string(),
+            weight: 400,
+            style: FontStyle::Normal,
+        }
+    }
+}
+
+impl TextFont {
+    pub fn from_description(desc: &pango::FontDescription) -> Self {
+        let style = match desc.style() {
+            pango::Style::Italic => FontStyle::Italic,
+            pango::Style::Oblique => FontStyle::Oblique,
+            _ => FontStyle::Normal,
+        };
+        Self {
+            family: desc
+                .family()
+                .map(|family| family.to_string())
+                .unwrap_or_else(|| "Sans".to_string()),
+            weight: desc.weight().into_glib(),
+            style,
+        }
+    }
+
+    pub fn description(&self, size: f64) -> pango::FontDescription {
+        let mut desc = pango::FontDescription::new();
+        desc.set_family(&self.family);
+        desc.set_weight(pango_weight(self.weight));
+        desc.set_style(match self.style {
+            FontStyle::Normal => pango::Style::Normal,
+            FontStyle::Italic => pango::Style::Italic,
+            FontStyle::Oblique => pango::Style::Oblique,
+        });
+        desc.set_absolute_size(size * pango::SCALE as f64);
+        desc
+    }
+
+    pub fn css_style(&self) -> &'static str {
+        match self.style {
+            FontStyle::Normal => "normal",
+            FontStyle::Italic => "italic",
+            FontStyle::Oblique => "oblique",
+        }
+    }
+}
+
+fn pango_weight(weight: i32) -> pango::Weight {
+    match weight {
+        ..=150 => pango::Weight::Thin,
+        151..=250 => pango::Weight::Ultralight,
+        251..=325 => pango::Weight::Light,
+        326..=365 => pango::Weight::Semilight,
+        366..=390 => pango::Weight::Book,
+        391..=450 => pango::Weight::Normal,
+        451..=550 => pango::Weight::Medium,
+        551..=650 => pango::Weight::Semibold,
+        651..=750 => pango::Weight::Bold,
+        751..=850 => pango::Weight::Ultrabold,
+        851..=950 => pango::Weight::Heavy,
+        _ => pango::Weight::Ultraheavy,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Tool {
     Select,
     Pen,
+    Highlighter,
     Rect,
+    Ellipse,
     Line,
     Arrow,
     Text,
+    Callout,
+    Step,
     Blur,
+    Magnify,
+    Spotlight,
+    Eyedropper,
     Crop,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+    Start,
+    End,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZOrder {
+    Forward,
+    Backward,
+    Front,
+    Back,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextEdit {
+    pub index: Option<usize>,
+    pub pos: Point,
+    pub tip: Option<Point>,
+}
+
+pub const DUPLICATE_OFFSET: f64 = 16.0;
+const HIGHLIGHTER_ALPHA: f32 = 0.5;
+const HIGHLIGHTER_WIDTH_SCALE: f64 = 4.0;
+const STEP_RADIUS_SCALE: f64 = 0.75;
+const CALLOUT_PADDING: f64 = 0.4;
+const REDACTION_BRUSH_SCALE: f64 = 6.0;
+pub const CALLOUT_OFFSET: f64 = 48.0;
+const MAGNIFY_SCALE: f64 = 2.0;
+const MAGNIFY_GAP: f64 = 24.0;
+const HANDLE_SIZE: f64 = 8.0;
+
+thread_local! {
+    static MEASURE_CONTEXT: pango::Context = pangocairo::FontMap::default().create_context();
+}
+
 pub struct EditorState {
     pub background: Option<Pixbuf>,
     pub annotations: Vec<Annotation>,
-    pub redo: Vec<Annotation>,
+    pub history: History,
     pub tool: Tool,
     pub color: gdk::RGBA,
     pub stroke_width: f64,
+    pub fill_enabled: bool,
+    pub fill_color: gdk::RGBA,
     pub text_size: f64,
+    pub text_font: TextFont,
+    pub blur_mode: BlurMode,
+    pub blur_strength: i32,
+    pub blur_shape: BlurShape,
+    pub blur_include_below: bool,
+    pub secure_export: bool,
+    pub magnify_connectors: bool,
+    pub spotlight_shape: SpotlightShape,
+    pub spotlight_opacity: f64,
     pub draft: Option<Annotation>,
     pub drag_start_view: Option<Point>,
     pub viewport_width: i32,
@@ -86,7 +339,12 @@ pub struct EditorState {
     pub zoom: f64,
     pub selected: Option<usize>,
     pub selected_original: Option<Annotation>,
+    pub drag_handle: Option<Handle>,
+    // Set while an Alt-drag moves a copy that hasn't been recorded in the history yet.
+    pub drag_duplicate: bool,
+    pub text_edit: Option<TextEdit>,
     pub crop_rect: Option<Rect>,
+    pub render_cache: RenderCache,
 }
 
 impl EditorState {
@@ -95,11 +353,22 @@ impl EditorState {
         Self {
             background: None,
             annotations: Vec::new(),
-            redo: Vec::new(),
+            history: History::default(),
             tool: Tool::Pen,
             color,
             stroke_width: 4.0,
+            fill_enabled: false,
+            fill_color: gdk::RGBA::new(1.0, 0.30, 0.30, 0.35),
             text_size: 22.0,
+            text_font: TextFont::default(),
+            blur_mode: BlurMode::Pixelate,
+            blur_strength: 10,
+            blur_shape: BlurShape::Rect,
+            blur_include_below: false,
+            secure_export: true,
+            magnify_connectors: true,
+            spotlight_shape: SpotlightShape::Rect,
+            spotlight_opacity: 0.6,
             draft: None,
             drag_start_view: None,
             viewport_width: 0,
@@ -108,35 +377,389 @@ impl EditorState {
             zoom: 1.0,
             selected: None,
             selected_original: None,
+            drag_handle: None,
+            drag_duplicate: false,
+            text_edit: None,
             crop_rect: None,
+            render_cache: RenderCache::default(),
         }
     }
 
     pub fn set_background(&mut self, pixbuf: Pixbuf) {
         self.background = Some(pixbuf);
         self.annotations.clear();
-        self.redo.clear();
+        self.history.clear();
         self.draft = None;
         self.drag_start_view = None;
+        self.drag_duplicate = false;
         self.selected = None;
         self.selected_original = None;
+        self.text_edit = None;
         self.crop_rect = None;
     }
 
+    pub fn fill(&self) -> Option<gdk::RGBA> {
+        self.fill_enabled.then_some(self.fill_color)
+    }
+
+    pub fn stroke_annotation(&self, point: Point) -> Annotation {
+        let highlighter = self.tool == Tool::Highlighter;
+        let (color, width) = if highlighter {
+            let color = gdk::RGBA::new(
+                self.color.red(),
+                self.color.green(),
+                self.color.blue(),
+                HIGHLIGHTER_ALPHA,
+            );
+            (color, self.stroke_width * HIGHLIGHTER_WIDTH_SCALE)
+        } else {
+            (self.color, self.stroke_width)
+        };
+        Annotation::Pen {
+            points: vec![point],
+            color,
+            width,
+            highlighter,
+        }
+    }
+
+    pub fn blur_annotation(&self, point: Point) -> Annotation {
+        let region = match self.blur_shape {
+            BlurShape::Rect => None,
+            BlurShape::Brush => Some(BlurRegion::Brush {
+                points: vec![point],
+                width: self.stroke_width * REDACTION_BRUSH_SCALE,
+            }),
+            BlurShape::Lasso => Some(BlurRegion::Polygon {
+                points: vec![point],
+            }),
+        };
+        let rect = region.as_ref().and_then(region_bounds).unwrap_or(Rect {
+            x1: point.x,
+            y1: point.y,
+            x2: point.x,
+            y2: point.y,
+        });
+        Annotation::Blur {
+            rect,
+            mode: self.blur_mode,
+            strength: self.blur_strength,
+            region,
+            include_below: self.blur_include_below,
+        }
+    }
+
+    pub fn magnify_annotation(&self, point: Point) -> Annotation {
+        let rect = Rect {
+            x1: point.x,
+            y1: point.y,
+            x2: point.x,
+            y2: point.y,
+        };
+        Annotation::Magnify {
+            source: rect,
+            rect,
+            color: self.color,
+            width: self.stroke_width,
+            connectors: self.magnify_connectors,
+        }
+    }
+
+    // Places the enlarged copy beside its source, preferring whichever side has room on the image.
+    pub fn place_magnifier(&self, annotation: &mut Annotation) -> bool {
+        let Annotation::Magnify { source, rect, .. } = annotation else {
+            return false;
+        };
+        let (x, y, w, h) = source.normalized();
+        if w < 2.0 || h < 2.0 {
+            return false;
+        }
+        let (max_w, max_h) = self
+            .background
+            .as_ref()
+            .map(|background| (background.width() as f64, background.height() as f64))
+            .unwrap_or((f64::INFINITY, f64::INFINITY));
+        let inset_w = w * MAGNIFY_SCALE;
+        let inset_h = h * MAGNIFY_SCALE;
+        let right = x + w + MAGNIFY_GAP;
+        let left = x - MAGNIFY_GAP - inset_w;
+        let below = y + h + MAGNIFY_GAP;
+        let (inset_x, inset_y) = if right + inset_w <= max_w {
+            (right, y)
+        } else if left >= 0.0 {
+            (left, y)
+        } else if below + inset_h <= max_h {
+            (x, below)
+        } else {
+            (x, y - MAGNIFY_GAP - inset_h)
+        };
+        let inset_x = inset_x.min(max_w - inset_w).max(0.0);
+        let inset_y = inset_y.min(max_h - inset_h).max(0.0);
+        *source = Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        };
+        *rect = Rect {
+            x1: inset_x,
+            y1: inset_y,
+            x2: inset_x + inset_w,
+            y2: inset_y + inset_h,
+        };
+        true
+    }
+
+    // An image has a single spotlight: further cut-outs are added to the topmost one instead
+    // of stacking another dimming layer. The existing spotlight is edited live and recorded
+    // as one Modify when the drag ends.
+    pub fn begin_spotlight(&mut self, point: Point) {
+        let hole = SpotlightHole {
+            rect: Rect {
+                x1: point.x,
+                y1: point.y,
+                x2: point.x,
+                y2: point.y,
+            },
+            shape: self.spotlight_shape,
+        };
+        let existing = self
+            .annotations
+            .iter()
+            .rposition(|annotation| matches!(annotation, Annotation::Spotlight { .. }));
+        let Some(index) = existing else {
+            self.draft = Some(Annotation::Spotlight {
+                holes: vec![hole],
+                opacity: self.spotlight_opacity,
+            });
+            return;
+        };
+        self.selected_original = Some(self.annotations[index].clone());
+        self.selected = Some(index);
+        if let Annotation::Spotlight { holes, opacity } = &mut self.annotations[index] {
+            holes.push(hole);
+            *opacity = self.spotlight_opacity;
+        }
+    }
+
+    pub fn extend_spotlight(&mut self, point: Point) {
+        let annotation = match self.draft.as_mut() {
+            Some(draft) => Some(draft),
+            None => self.selected.and_then(|index| self.annotations.get_mut(index)),
+        };
+        if let Some(Annotation::Spotlight { holes, .. }) = annotation
+            && let Some(hole) = holes.last_mut()
+        {
+            hole.rect.x2 = point.x;
+            hole.rect.y2 = point.y;
+        }
+    }
+
+    pub fn finish_spotlight(&mut self, point: Point) {
+        self.extend_spotlight(point);
+        let placed = |annotation: &Annotation| match annotation {
+            Annotation::Spotlight { holes, .. } => holes.last().is_some_and(|hole| {
+                let (_, _, w, h) = hole.rect.normalized();
+                w >= 2.0 && h >= 2.0
+            }),
+            _ => false,
+        };
+        if let Some(draft) = self.draft.take() {
+            if placed(&draft) {
+                self.push_annotation(draft);
+            }
+            return;
+        }
+        let (Some(index), Some(before)) = (self.selected.take(), self.selected_original.take())
+        else {
+            return;
+        };
+        let Some(after) = self.annotations.get(index).cloned() else {
+            return;
+        };
+        if placed(&after) {
+            self.record(Command::Modify {
+                index,
+                before,
+                after,
+            });
+        } else {
+            self.annotations[index] = before;
+        }
+    }
+
+    pub fn toggle_selected_blur_below(&mut self) -> bool {
+        let Some(index) = self.selected else {
+            return false;
+        };
+        let Some(before) = self.annotations.get(index).cloned() else {
+            return false;
+        };
+        let mut after = before.clone();
+        let Annotation::Blur { include_below, .. } = &mut after else {
+            return false;
+        };
+        *include_below = !*include_below;
+        self.execute(Command::Modify {
+            index,
+            before,
+            after,
+        });
+        self.selected_original = self.annotations.get(index).cloned();
+        true
+    }
+
+    pub fn add_step(&mut self, center: Point) {
+        self.push_annotation(Annotation::Step {
+            center,
+            radius: (self.text_size * STEP_RADIUS_SCALE).max(8.0),
+            color: self.color,
+        });
+    }
+
     pub fn push_annotation(&mut self, annotation: Annotation) {
+        let index = self.annotations.len();
+        self.execute(Command::Add { index, annotation });
+    }
+
+    pub fn execute(&mut self, command: Command) {
+        history::apply(self, &command);
+        self.history.push(command);
+    }
+
+    pub fn record(&mut self, command: Command) {
+        self.history.push(command);
+    }
+
+    pub fn delete_selected(&mut self) -> bool {
+        let Some(index) = self.selected else {
+            return false;
+        };
+        let Some(annotation) = self.annotations.get(index).cloned() else {
+            return false;
+        };
+        self.execute(Command::Remove { index, annotation });
+        self.selected = None;
+        self.selected_original = None;
+        true
+    }
+
+    pub fn duplicate_selected(&mut self, dx: f64, dy: f64) -> bool {
+        let Some(mut annotation) = self.selected.and_then(|index| self.annotations.get(index).cloned())
+        else {
+            return false;
+        };
+        move_annotation(&mut annotation, dx, dy);
+        let index = self.annotations.len();
+        self.execute(Command::Add { index, annotation });
+        self.selected = Some(index);
+        self.selected_original = self.annotations.get(index).cloned();
+        true
+    }
+
+    // The copy is recorded as a single Add where it was dropped, once the drag ends.
+    pub fn begin_duplicate_drag(&mut self) -> bool {
+        let Some(annotation) = self.selected.and_then(|index| self.annotations.get(index).cloned())
+        else {
+            return false;
+        };
         self.annotations.push(annotation);
-        self.redo.clear();
+        self.selected = Some(self.annotations.len() - 1);
+        self.drag_duplicate = true;
+        true
+    }
+
+    pub fn reorder_selected(&mut self, order: ZOrder) -> bool {
+        let Some(from) = self.selected else {
+            return false;
+        };
+        let last = self.annotations.len().saturating_sub(1);
+        let to = match order {
+            ZOrder::Forward => (from + 1).min(last),
+            ZOrder::Backward => from.saturating_sub(1),
+            ZOrder::Front => last,
+            ZOrder::Back => 0,
+        };
+        if to == from || from > last {
+            return false;
+        }
+        self.execute(Command::Reorder { from, to });
+        self.selected = Some(to);
+        self.selected_original = self.annotations.get(to).cloned();
+        true
+    }
+
+    pub fn commit_text(&mut self, text: &str) -> bool {
+        let Some(edit) = self.text_edit.take() else {
+            return false;
+        };
+        let text = text.trim_end().to_string();
+        let Some(index) = edit.index else {
+            if text.trim().is_empty() {
+                return true;
+            }
+            let annotation = match edit.tip {
+                Some(tip) => Annotation::Callout {
+                    pos: edit.pos,
+                    tip,
+                    text,
+                    color: self.color,
+                    size: self.text_size,
+                    font: self.text_font.clone(),
+                },
+                None => Annotation::Text {
+                    pos: edit.pos,
+                    text,
+                    color: self.color,
+                    size: self.text_size,
+                    font: self.text_font.clone(),
+                },
+            };
+            self.push_annotation(annotation);
+            return true;
+        };
+        let Some(before) = self.annotations.get(index).cloned() else {
+            return true;
+        };
+        if text.trim().is_empty() {
+            self.execute(Command::Remove {
+                index,
+                annotation: before,
+            });
+            self.selected = None;
+            self.selected_original = None;
+            return true;
+        }
+        let mut after = before.clone();
+        if let Annotation::Text { text: current, .. } | Annotation::Callout { text: current, .. } =
+            &mut after
+            && *current != text
+        {
+            *current = text;
+            self.execute(Command::Modify {
+                index,
+                before,
+                after,
+            });
+        }
+        true
     }
 
     pub fn undo(&mut self) {
-        if let Some(last) = self.annotations.pop() {
-            self.redo.push(last);
+        if let Some(command) = self.history.pop_undo() {
+            history::revert(self, &command);
+            self.history.push_redo(command);
+            self.selected = None;
+            self.selected_original = None;
         }
     }
 
     pub fn redo(&mut self) {
-        if let Some(next) = self.redo.pop() {
-            self.annotations.push(next);
+        if let Some(command) = self.history.pop_redo() {
+            history::apply(self, &command);
+            self.history.push_undo(command);
+            self.selected = None;
+            self.selected_original = None;
         }
     }
 }
@@ -147,17 +770,19 @@ pub fn draw(state: &EditorState, ctx: &cairo::Context) {
     ctx.translate(offset_x, offset_y);
     ctx.scale(scale, scale);
 
-    if let Some(bg) = state.background.as_ref() {
-        ctx.set_source_pixbuf(bg, 0.0, 0.0);
-        let _ = ctx.paint();
-    }
-
-    for annotation in state
-        .annotations
-        .iter()
-        .chain(state.draft.iter())
-    {
-        draw_annotation(ctx, annotation, state.background.as_ref());
+    draw_scene(state, ctx);
+    if let Some(draft) = state.draft.as_ref() {
+        let step = step_number(&state.annotations, state.annotations.len());
+        let below = if samples_below(draft) {
+            state.background.as_ref().and_then(|background| {
+                let layers = 0..state.annotations.len();
+                composite_below(state, background, false, layers, Some(&state.render_cache))
+            })
+        } else {
+            None
+        };
+        let background = below.as_ref().or(state.background.as_ref());
+        draw_annotation(ctx, draft, background, step, Some(&state.render_cache));
     }
 
     if let Some(rect) = state.crop_rect {
@@ -170,8 +795,8 @@ pub fn draw(state: &EditorState, ctx: &cairo::Context) {
         let _ = ctx.restore();
     }
 
-    if let Some(index) = state.selected {
-        if let Some(bounds) = annotation_bounds(&state.annotations[index]) {
+    if let Some(annotation) = state.selected.and_then(|index| state.annotations.get(index)) {
+        if let Some(bounds) = annotation_bounds(annotation) {
             let (x, y, w, h) = bounds.normalized();
             let _ = ctx.save();
             ctx.set_source_rgba(0.8, 0.8, 1.0, 0.6);
@@ -180,21 +805,172 @@ pub fn draw(state: &EditorState, ctx: &cairo::Context) {
             let _ = ctx.stroke();
             let _ = ctx.restore();
         }
+        draw_handles(ctx, annotation, scale);
     }
     let _ = ctx.restore();
 }
 
+fn draw_scene(state: &EditorState, ctx: &cairo::Context) {
+    let layers = 0..state.annotations.len();
+    let cache = Some(&state.render_cache);
+    draw_layers(state, ctx, state.background.as_ref(), false, layers, cache);
+}
+
+fn draw_layers(
+    state: &EditorState,
+    ctx: &cairo::Context,
+    background: Option<&Pixbuf>,
+    redactions_baked: bool,
+    layers: Range<usize>,
+    cache: Option<&RenderCache>,
+) {
+    if let Some(bg) = background {
+        let (device_scale, _) = ctx.target().device_scale();
+        let scale = ctx.matrix().xx() * device_scale;
+        match cache.and_then(|cache| cache.background(bg, scale)) {
+            Some((surface, factor)) => {
+                let _ = ctx.save();
+                ctx.scale(1.0 / factor, 1.0 / factor);
+                let _ = ctx.set_source_surface(&surface, 0.0, 0.0);
+                let _ = ctx.paint();
+                let _ = ctx.restore();
+            }
+            None => {
+                ctx.set_source_pixbuf(bg, 0.0, 0.0);
+                let _ = ctx.paint();
+            }
+        }
+    }
+
+    let editing = state.text_edit.and_then(|edit| edit.index);
+    let mut step = 0;
+    for (index, annotation) in state.annotations.iter().enumerate().take(layers.end) {
+        if matches!(annotation, Annotation::Step { .. }) {
+            step += 1;
+        }
+        if index < layers.start
+            || Some(index) == editing
+            || (redactions_baked && matches!(annotation, Annotation::Blur { .. }))
+        {
+            continue;
+        }
+        // Blurs that include lower annotations sample a flattened render of everything beneath them.
+        if samples_below(annotation)
+            && let Some(below) = background.and_then(|background| {
+                let below = layers.start..index;
+                composite_below(state, background, redactions_baked, below, cache)
+            })
+        {
+            draw_annotation(ctx, annotation, Some(&below), step, cache);
+            continue;
+        }
+        draw_annotation(ctx, annotation, background, step, cache);
+    }
+}
+
+// Magnifiers always sample the flattened scene so they enlarge redactions rather than revealing
+// what's under them.
+fn samples_below(annotation: &Annotation) -> bool {
+    matches!(
+        annotation,
+        Annotation::Blur {
+            include_below: true,
+            ..
+        } | Annotation::Magnify { .. }
+    )
+}
+
+// Steps are numbered by their position in the stack, so deleting or reordering renumbers them.
+pub fn step_number(annotations: &[Annotation], index: usize) -> usize {
+    annotations
+        .iter()
+        .take(index)
+        .filter(|annotation| matches!(annotation, Annotation::Step { .. }))
+        .count()
+        + 1
+}
+
 pub fn render_to_pixbuf(state: &EditorState) -> Option<Pixbuf> {
-    let background = state.background.as_ref()?;
+    render_layers(state, state.background.as_ref()?, false, 0..state.annotations.len())
+}
+
+// The result is a fresh pixbuf read back from a cairo surface, so no loader options
+// (EXIF, text chunks, ICC profiles) from the source image are carried into the export.
+pub fn render_secure(state: &EditorState) -> Result<Pixbuf, String> {
+    redact::secure_render(state)
+}
+
+pub fn export_pixbuf(state: &EditorState) -> Result<Pixbuf, String> {
+    if state.secure_export {
+        render_secure(state)
+    } else {
+        render_to_pixbuf(state).ok_or_else(|| "Nothing to export yet.".to_string())
+    }
+}
+
+pub fn render_layers(
+    state: &EditorState,
+    background: &Pixbuf,
+    redactions_baked: bool,
+    layers: Range<usize>,
+) -> Option<Pixbuf> {
+    render_layers_cached(state, background, redactions_baked, layers, None)
+}
+
+fn render_layers_cached(
+    state: &EditorState,
+    background: &Pixbuf,
+    redactions_baked: bool,
+    layers: Range<usize>,
+    cache: Option<&RenderCache>,
+) -> Option<Pixbuf> {
     let width = background.width();
     let height = background.height();
     let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height).ok()?;
     let ctx = cairo::Context::new(&surface).ok()?;
-    draw(state, &ctx);
+    draw_layers(state, &ctx, Some(background), redactions_baked, layers, cache);
     #[allow(deprecated)]
     gtk::gdk::pixbuf_get_from_surface(&surface, 0, 0, width, height)
 }
 
+// Draws the annotations in `layers` with nothing beneath them, so the alpha shows which pixels
+// they paint over. Magnifier insets sample the image, so they're filled whole.
+pub fn paint_coverage(state: &EditorState, ctx: &cairo::Context, layers: Range<usize>) {
+    draw_layers(state, ctx, None, true, layers.clone(), None);
+    for annotation in state.annotations.get(layers).unwrap_or_default() {
+        if let Annotation::Magnify { rect, .. } = annotation {
+            let (x, y, w, h) = rect.normalized();
+            ctx.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+            ctx.rectangle(x, y, w, h);
+            let _ = ctx.fill();
+        }
+    }
+}
+
+// Cached composites are keyed on a hash of the annotations they flatten, so editing
+// anything beneath an include-below blur produces a new key rather than a stale image,
+// while dragging annotations above it keeps hitting the cache.
+fn composite_below(
+    state: &EditorState,
+    background: &Pixbuf,
+    redactions_baked: bool,
+    layers: Range<usize>,
+    cache: Option<&RenderCache>,
+) -> Option<Pixbuf> {
+    let Some(cache) = cache else {
+        return render_layers(state, background, redactions_baked, layers);
+    };
+    let editing = state.text_edit.and_then(|edit| edit.index);
+    let annotations = state.annotations.get(layers.clone()).unwrap_or_default();
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(annotations).ok()?.hash(&mut hasher);
+    let contents = hasher.finish();
+    let key = format!("{redactions_baked}:{layers:?}:{editing:?}:{contents:x}");
+    cache.composite(background, key, || {
+        render_layers_cached(state, background, redactions_baked, layers, Some(cache))
+    })
+}
+
 pub fn view_transform(state: &EditorState) -> (f64, f64, f64) {
     let Some(background) = state.background.as_ref() else {
         return (1.0, 0.0, 0.0);
@@ -224,12 +1000,42 @@ pub fn map_to_image(state: &EditorState, x: f64, y: f64) -> Point {
     }
 }
 
-fn draw_annotation(ctx: &cairo::Context, annotation: &Annotation, background: Option<&Pixbuf>) {
+// Reads a single pixel through a 1x1 subpixbuf so hovering doesn't copy the whole image.
+pub fn sample_color(state: &EditorState, point: Point) -> Option<gdk::RGBA> {
+    let background = state.background.as_ref()?;
+    let x = point.x.floor() as i32;
+    let y = point.y.floor() as i32;
+    if x >= background.width() || y >= background.height() {
+        return None;
+    }
+    let pixel = Pixbuf::new_subpixbuf(background, x, y, 1, 1).read_pixel_bytes();
+    let channel = |index: usize| pixel.get(index).copied().unwrap_or(255) as f32 / 255.0;
+    Some(gdk::RGBA::new(channel(0), channel(1), channel(2), 1.0))
+}
+
+pub fn hex_color(color: &gdk::RGBA) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.red()),
+        channel(color.green()),
+        channel(color.blue())
+    )
+}
+
+fn draw_annotation(
+    ctx: &cairo::Context,
+    annotation: &Annotation,
+    background: Option<&Pixbuf>,
+    step: usize,
+    cache: Option<&RenderCache>,
+) {
     match annotation {
         Annotation::Pen {
             points,
             color,
             width,
+            highlighter,
         } => {
             if points.len() < 2 {
                 return;
@@ -237,7 +1043,13 @@ fn draw_annotation(ctx: &cairo::Context, annotation: &Annotation, background: Op
             let _ = ctx.save();
             set_source_rgba(ctx, color);
             ctx.set_line_width(*width);
-            ctx.set_line_cap(cairo::LineCap::Round);
+            if *highlighter {
+                // Multiply keeps dark text under the stroke legible.
+                ctx.set_operator(cairo::Operator::Multiply);
+                ctx.set_line_cap(cairo::LineCap::Butt);
+            } else {
+                ctx.set_line_cap(cairo::LineCap::Round);
+            }
             ctx.set_line_join(cairo::LineJoin::Round);
             ctx.move_to(points[0].x, points[0].y);
             for point in points.iter().skip(1) {
@@ -250,13 +1062,31 @@ fn draw_annotation(ctx: &cairo::Context, annotation: &Annotation, background: Op
             rect,
             color,
             width,
+            fill,
         } => {
             let (x, y, w, h) = rect.normalized();
             let _ = ctx.save();
-            set_source_rgba(ctx, color);
-            ctx.set_line_width(*width);
             ctx.rectangle(x, y, w, h);
-            let _ = ctx.stroke();
+            fill_and_stroke(ctx, color, *width, fill.as_ref());
+            let _ = ctx.restore();
+        }
+        Annotation::Ellipse {
+            rect,
+            color,
+            width,
+            fill,
+        } => {
+            let (x, y, w, h) = rect.normalized();
+            if w < 1.0 || h < 1.0 {
+                return;
+            }
+            let _ = ctx.save();
+            let matrix = ctx.matrix();
+            ctx.translate(x + w / 2.0, y + h / 2.0);
+            ctx.scale(w / 2.0, h / 2.0);
+            ctx.arc(0.0, 0.0, 1.0, 0.0, std::f64::consts::TAU);
+            ctx.set_matrix(matrix);
+            fill_and_stroke(ctx, color, *width, fill.as_ref());
             let _ = ctx.restore();
         }
         Annotation::Line {
@@ -283,77 +1113,689 @@ fn draw_annotation(ctx: &cairo::Context, annotation: &Annotation, background: Op
             text,
             color,
             size,
+            font,
+        } => {
+            let _ = ctx.save();
+            set_source_rgba(ctx, color);
+            let layout = pangocairo::functions::create_layout(ctx);
+            configure_text_layout(&layout, text, *size, font);
+            ctx.move_to(pos.x, pos.y - pango_units(layout.baseline()));
+            pangocairo::functions::show_layout(ctx, &layout);
+            let _ = ctx.restore();
+        }
+        Annotation::Callout {
+            pos,
+            tip,
+            text,
+            color,
+            size,
+            font,
+        } => {
+            let body = callout_box(*pos, text, *size, font);
+            let (x, y, w, h) = body.normalized();
+            let _ = ctx.save();
+            // Composite the bubble and tail as one shape so translucent colors don't double up.
+            ctx.push_group();
+            ctx.set_source_rgb(color.red() as f64, color.green() as f64, color.blue() as f64);
+            rounded_rectangle(ctx, x, y, w, h, size * CALLOUT_PADDING);
+            let _ = ctx.fill();
+            let center = Point {
+                x: x + w / 2.0,
+                y: y + h / 2.0,
+            };
+            let dx = tip.x - center.x;
+            let dy = tip.y - center.y;
+            let len = (dx * dx + dy * dy).sqrt();
+            if len > 0.1 {
+                let half = (w.min(h) / 4.0).max(2.0);
+                let nx = -dy / len * half;
+                let ny = dx / len * half;
+                ctx.move_to(center.x + nx, center.y + ny);
+                ctx.line_to(tip.x, tip.y);
+                ctx.line_to(center.x - nx, center.y - ny);
+                ctx.close_path();
+                let _ = ctx.fill();
+            }
+            let _ = ctx.pop_group_to_source();
+            let _ = ctx.paint_with_alpha(color.alpha() as f64);
+            set_source_rgba(ctx, &contrast_color(color));
+            let layout = pangocairo::functions::create_layout(ctx);
+            configure_text_layout(&layout, text, *size, font);
+            ctx.move_to(pos.x, pos.y - pango_units(layout.baseline()));
+            pangocairo::functions::show_layout(ctx, &layout);
+            let _ = ctx.restore();
+        }
+        Annotation::Step {
+            center,
+            radius,
+            color,
         } => {
             let _ = ctx.save();
+            ctx.arc(center.x, center.y, *radius, 0.0, std::f64::consts::TAU);
             set_source_rgba(ctx, color);
-            ctx.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
-            ctx.set_font_size(*size);
-            ctx.move_to(pos.x, pos.y);
-            let _ = ctx.show_text(text);
+            let _ = ctx.fill();
+            let font = TextFont {
+                weight: 700,
+                ..TextFont::default()
+            };
+            let layout = pangocairo::functions::create_layout(ctx);
+            configure_text_layout(&layout, &step.to_string(), radius * 1.1, &font);
+            let (_, logical) = layout.extents();
+            ctx.move_to(
+                center.x - pango_units(logical.x() + logical.width() / 2),
+                center.y - pango_units(logical.y() + logical.height() / 2),
+            );
+            set_source_rgba(ctx, &contrast_color(color));
+            pangocairo::functions::show_layout(ctx, &layout);
             let _ = ctx.restore();
         }
-        Annotation::Blur { rect, pixel_size } => {
+        Annotation::Blur {
+            rect,
+            mode,
+            strength,
+            region,
+            ..
+        } => {
             if let Some(background) = background {
-                draw_pixelate(ctx, *rect, *pixel_size, background);
+                let region = region.as_ref();
+                draw_redaction(ctx, *rect, region, *mode, *strength, background, cache);
+            }
+        }
+        Annotation::Magnify {
+            source,
+            rect,
+            color,
+            width,
+            connectors,
+        } => {
+            draw_magnifier(ctx, *source, *rect, color, *width, *connectors, background);
+        }
+        Annotation::Spotlight { holes, opacity } => {
+            let _ = ctx.save();
+            match background {
+                Some(background) => {
+                    ctx.rectangle(0.0, 0.0, background.width() as f64, background.height() as f64);
+                }
+                None => {
+                    let (x1, y1, x2, y2) = ctx.clip_extents().unwrap_or_default();
+                    ctx.rectangle(x1, y1, x2 - x1, y2 - y1);
+                }
             }
+            for hole in holes {
+                let (x, y, w, h) = hole.rect.normalized();
+                if w < 1.0 || h < 1.0 {
+                    continue;
+                }
+                match hole.shape {
+                    SpotlightShape::Rect => ctx.rectangle(x, y, w, h),
+                    SpotlightShape::Ellipse => {
+                        let matrix = ctx.matrix();
+                        ctx.new_sub_path();
+                        ctx.translate(x + w / 2.0, y + h / 2.0);
+                        ctx.scale(w / 2.0, h / 2.0);
+                        ctx.arc(0.0, 0.0, 1.0, 0.0, std::f64::consts::TAU);
+                        ctx.close_path();
+                        ctx.set_matrix(matrix);
+                    }
+                }
+            }
+            ctx.set_fill_rule(cairo::FillRule::EvenOdd);
+            ctx.set_source_rgba(0.0, 0.0, 0.0, *opacity);
+            let _ = ctx.fill();
+            let _ = ctx.restore();
         }
     }
 }
 
-pub fn annotation_bounds(annotation: &Annotation) -> Option<Rect> {
-    match annotation {
-        Annotation::Pen { points, .. } => {
-            let mut min_x = f64::INFINITY;
-            let mut min_y = f64::INFINITY;
-            let mut max_x = f64::NEG_INFINITY;
-            let mut max_y = f64::NEG_INFINITY;
-            for point in points {
-                min_x = min_x.min(point.x);
-                min_y = min_y.min(point.y);
-                max_x = max_x.max(point.x);
-                max_y = max_y.max(point.y);
-            }
-            if min_x.is_finite() {
-                Some(Rect {
-                    x1: min_x,
-                    y1: min_y,
-                    x2: max_x,
-                    y2: max_y,
-                })
-            } else {
-                None
-            }
+pub fn extend_blur(annotation: &mut Annotation, point: Point) {
+    let Annotation::Blur { rect, region, .. } = annotation else {
+        return;
+    };
+    match region {
+        Some(BlurRegion::Brush { points, .. } | BlurRegion::Polygon { points }) => {
+            points.push(point);
         }
-        Annotation::Rect { rect, .. } => Some(*rect),
-        Annotation::Line { start, end, .. } => Some(Rect {
-            x1: start.x,
-            y1: start.y,
-            x2: end.x,
-            y2: end.y,
-        }),
-        Annotation::Text { pos, text, size, .. } => {
-            let width = (text.len() as f64 * size * 0.6).max(1.0);
-            Some(Rect {
-                x1: pos.x,
-                y1: pos.y - size,
-                x2: pos.x + width,
-                y2: pos.y + size * 0.2,
-            })
+        None => {
+            rect.x2 = point.x;
+            rect.y2 = point.y;
         }
-        Annotation::Blur { rect, .. } => Some(*rect),
+    }
+    if let Some(region) = region.as_ref()
+        && let Some(bounds) = region_bounds(region)
+    {
+        *rect = bounds;
     }
 }
 
+fn region_bounds(region: &BlurRegion) -> Option<Rect> {
+    let (points, margin) = match region {
+        BlurRegion::Brush { points, width } => (points, width / 2.0),
+        BlurRegion::Polygon { points } => (points, 0.0),
+    };
+    let first = points.first()?;
+    let mut bounds = Rect {
+        x1: first.x,
+        y1: first.y,
+        x2: first.x,
+        y2: first.y,
+    };
+    for point in points {
+        bounds.x1 = bounds.x1.min(point.x);
+        bounds.y1 = bounds.y1.min(point.y);
+        bounds.x2 = bounds.x2.max(point.x);
+        bounds.y2 = bounds.y2.max(point.y);
+    }
+    Some(Rect {
+        x1: bounds.x1 - margin,
+        y1: bounds.y1 - margin,
+        x2: bounds.x2 + margin,
+        y2: bounds.y2 + margin,
+    })
+}
+
+// Fills (or, for brush strokes, strokes) the redaction shape with the current source.
+pub fn paint_redaction_shape(ctx: &cairo::Context, rect: Rect, region: Option<&BlurRegion>) {
+    match region {
+        Some(BlurRegion::Brush { points, width }) => {
+            let Some(first) = points.first() else {
+                return;
+            };
+            ctx.set_line_width(*width);
+            ctx.set_line_cap(cairo::LineCap::Round);
+            ctx.set_line_join(cairo::LineJoin::Round);
+            ctx.move_to(first.x, first.y);
+            for point in points.iter().skip(1) {
+                ctx.line_to(point.x, point.y);
+            }
+            if points.len() == 1 {
+                ctx.line_to(first.x, first.y);
+            }
+            let _ = ctx.stroke();
+        }
+        Some(BlurRegion::Polygon { points }) => {
+            if points.len() < 3 {
+                return;
+            }
+            ctx.move_to(points[0].x, points[0].y);
+            for point in points.iter().skip(1) {
+                ctx.line_to(point.x, point.y);
+            }
+            ctx.close_path();
+            let _ = ctx.fill();
+        }
+        None => {
+            let (x, y, w, h) = rect.normalized();
+            ctx.rectangle(x, y, w, h);
+            let _ = ctx.fill();
+        }
+    }
+}
+
+pub fn annotation_bounds(annotation: &Annotation) -> Option<Rect> {
+    match annotation {
+        Annotation::Pen { points, .. } => {
+            let mut min_x = f64::INFINITY;
+            let mut min_y = f64::INFINITY;
+            let mut max_x = f64::NEG_INFINITY;
+            let mut max_y = f64::NEG_INFINITY;
+            for point in points {
+                min_x = min_x.min(point.x);
+                min_y = min_y.min(point.y);
+                max_x = max_x.max(point.x);
+                max_y = max_y.max(point.y);
+            }
+            if min_x.is_finite() {
+                Some(Rect {
+                    x1: min_x,
+                    y1: min_y,
+                    x2: max_x,
+                    y2: max_y,
+                })
+            } else {
+                None
+            }
+        }
+        Annotation::Rect { rect, .. } | Annotation::Ellipse { rect, .. } => Some(*rect),
+        Annotation::Line { start, end, .. } => Some(Rect {
+            x1: start.x,
+            y1: start.y,
+            x2: end.x,
+            y2: end.y,
+        }),
+        Annotation::Text {
+            pos,
+            text,
+            size,
+            font,
+            ..
+        } => Some(text_bounds(*pos, text, *size, font)),
+        Annotation::Callout {
+            pos,
+            tip,
+            text,
+            size,
+            font,
+            ..
+        } => {
+            let body = callout_box(*pos, text, *size, font);
+            Some(Rect {
+                x1: body.x1.min(tip.x),
+                y1: body.y1.min(tip.y),
+                x2: body.x2.max(tip.x),
+                y2: body.y2.max(tip.y),
+            })
+        }
+        Annotation::Step { center, radius, .. } => Some(Rect {
+            x1: center.x - radius,
+            y1: center.y - radius,
+            x2: center.x + radius,
+            y2: center.y + radius,
+        }),
+        Annotation::Blur { rect, .. } => Some(*rect),
+        Annotation::Magnify { source, rect, .. } => {
+            let (sx, sy, sw, sh) = source.normalized();
+            let (x, y, w, h) = rect.normalized();
+            Some(Rect {
+                x1: sx.min(x),
+                y1: sy.min(y),
+                x2: (sx + sw).max(x + w),
+                y2: (sy + sh).max(y + h),
+            })
+        }
+        Annotation::Spotlight { holes, .. } => holes
+            .iter()
+            .map(|hole| {
+                let (x, y, w, h) = hole.rect.normalized();
+                Rect {
+                    x1: x,
+                    y1: y,
+                    x2: x + w,
+                    y2: y + h,
+                }
+            })
+            .reduce(|bounds, rect| Rect {
+                x1: bounds.x1.min(rect.x1),
+                y1: bounds.y1.min(rect.y1),
+                x2: bounds.x2.max(rect.x2),
+                y2: bounds.y2.max(rect.y2),
+            }),
+    }
+}
+
+fn configure_text_layout(layout: &pango::Layout, text: &str, size: f64, font: &TextFont) {
+    layout.set_font_description(Some(&font.description(size)));
+    layout.set_text(text);
+}
+
+// Text positions are the baseline of the first line, so layouts are shifted up by their baseline.
+pub fn text_bounds(pos: Point, text: &str, size: f64, font: &TextFont) -> Rect {
+    MEASURE_CONTEXT.with(|context| {
+        let layout = pango::Layout::new(context);
+        configure_text_layout(&layout, text, size, font);
+        let (_, logical) = layout.extents();
+        let x = pos.x + pango_units(logical.x());
+        let y = pos.y - pango_units(layout.baseline()) + pango_units(logical.y());
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + pango_units(logical.width()).max(1.0),
+            y2: y + pango_units(logical.height()).max(1.0),
+        }
+    })
+}
+
+fn callout_box(pos: Point, text: &str, size: f64, font: &TextFont) -> Rect {
+    let bounds = text_bounds(pos, text, size, font);
+    let padding = size * CALLOUT_PADDING;
+    Rect {
+        x1: bounds.x1 - padding,
+        y1: bounds.y1 - padding,
+        x2: bounds.x2 + padding,
+        y2: bounds.y2 + padding,
+    }
+}
+
+pub fn contrast_color(color: &gdk::RGBA) -> gdk::RGBA {
+    let luminance = 0.299 * color.red() + 0.587 * color.green() + 0.114 * color.blue();
+    if luminance > 0.6 {
+        gdk::RGBA::BLACK
+    } else {
+        gdk::RGBA::WHITE
+    }
+}
+
+fn pango_units(value: i32) -> f64 {
+    value as f64 / pango::SCALE as f64
+}
+
+// A spotlight hole usually frames the annotations it highlights, so anything hit beneath a
+// spotlight wins over the spotlight itself.
 pub fn hit_test(annotations: &[Annotation], point: Point) -> Option<usize> {
+    let mut spotlight = None;
     for (index, annotation) in annotations.iter().enumerate().rev() {
         if let Some(bounds) = annotation_bounds(annotation) {
             let (x, y, w, h) = bounds.normalized();
-            if point.x >= x && point.x <= x + w && point.y >= y && point.y <= y + h {
+            if point.x >= x
+                && point.x <= x + w
+                && point.y >= y
+                && point.y <= y + h
+                && hit_test_shape(annotation, point)
+            {
+                if matches!(annotation, Annotation::Spotlight { .. }) {
+                    spotlight.get_or_insert(index);
+                    continue;
+                }
                 return Some(index);
             }
         }
     }
-    None
+    spotlight
+}
+
+fn hit_test_shape(annotation: &Annotation, point: Point) -> bool {
+    if let Annotation::Callout {
+        pos,
+        tip,
+        text,
+        size,
+        font,
+        ..
+    } = annotation
+    {
+        let (x, y, w, h) = callout_box(*pos, text, *size, font).normalized();
+        if point.x >= x && point.x <= x + w && point.y >= y && point.y <= y + h {
+            return true;
+        }
+        let center = Point {
+            x: x + w / 2.0,
+            y: y + h / 2.0,
+        };
+        return distance_to_segment(point, center, *tip) <= (w.min(h) / 4.0).max(HANDLE_SIZE);
+    }
+    if let Annotation::Blur {
+        region: Some(region),
+        ..
+    } = annotation
+    {
+        return match region {
+            BlurRegion::Brush { points, width } => {
+                let reach = (width / 2.0).max(HANDLE_SIZE);
+                match points.as_slice() {
+                    [only] => distance_to_segment(point, *only, *only) <= reach,
+                    _ => points
+                        .windows(2)
+                        .any(|pair| distance_to_segment(point, pair[0], pair[1]) <= reach),
+                }
+            }
+            BlurRegion::Polygon { points } => point_in_polygon(point, points),
+        };
+    }
+    if let Annotation::Magnify { source, rect, .. } = annotation {
+        return [source, rect].into_iter().any(|rect| {
+            let (x, y, w, h) = rect.normalized();
+            point.x >= x && point.x <= x + w && point.y >= y && point.y <= y + h
+        });
+    }
+    // Spotlights are picked through their cut-outs, since the dimmed area covers the whole image.
+    if let Annotation::Spotlight { holes, .. } = annotation {
+        return holes.iter().any(|hole| {
+            let (x, y, w, h) = hole.rect.normalized();
+            point.x >= x && point.x <= x + w && point.y >= y && point.y <= y + h
+        });
+    }
+    let Annotation::Ellipse { rect, width, .. } = annotation else {
+        return true;
+    };
+    let (x, y, w, h) = rect.normalized();
+    let rx = w / 2.0 + width / 2.0;
+    let ry = h / 2.0 + width / 2.0;
+    if rx <= 0.0 || ry <= 0.0 {
+        return false;
+    }
+    let dx = (point.x - x - w / 2.0) / rx;
+    let dy = (point.y - y - h / 2.0) / ry;
+    dx * dx + dy * dy <= 1.0
+}
+
+fn point_in_polygon(point: Point, polygon: &[Point]) -> bool {
+    let mut inside = false;
+    let mut previous = match polygon.last() {
+        Some(last) => *last,
+        None => return false,
+    };
+    for current in polygon {
+        if (current.y > point.y) != (previous.y > point.y)
+            && point.x
+                < (previous.x - current.x) * (point.y - current.y) / (previous.y - current.y)
+                    + current.x
+        {
+            inside = !inside;
+        }
+        previous = *current;
+    }
+    inside
+}
+
+fn distance_to_segment(point: Point, start: Point, end: Point) -> f64 {
+    let dx = end.x - start.x;
+    let dy = end.y - start.y;
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq <= f64::EPSILON {
+        0.0
+    } else {
+        (((point.x - start.x) * dx + (point.y - start.y) * dy) / len_sq).clamp(0.0, 1.0)
+    };
+    let px = start.x + t * dx - point.x;
+    let py = start.y + t * dy - point.y;
+    (px * px + py * py).sqrt()
+}
+
+pub fn annotation_handles(annotation: &Annotation) -> Vec<(Handle, Point)> {
+    match annotation {
+        Annotation::Line { start, end, .. } => vec![(Handle::Start, *start), (Handle::End, *end)],
+        Annotation::Callout { tip, .. } => vec![(Handle::End, *tip)],
+        Annotation::Text { .. } => Vec::new(),
+        Annotation::Magnify { source, rect, .. } => {
+            let (sx, sy, sw, sh) = source.normalized();
+            let mut handles = rect_handles(*rect);
+            handles.push((Handle::Start, Point { x: sx, y: sy }));
+            handles.push((
+                Handle::End,
+                Point {
+                    x: sx + sw,
+                    y: sy + sh,
+                },
+            ));
+            handles
+        }
+        _ => annotation_bounds(annotation)
+            .map(rect_handles)
+            .unwrap_or_default(),
+    }
+}
+
+fn rect_handles(bounds: Rect) -> Vec<(Handle, Point)> {
+    let (x, y, w, h) = bounds.normalized();
+    let cx = x + w / 2.0;
+    let cy = y + h / 2.0;
+    vec![
+        (Handle::TopLeft, Point { x, y }),
+        (Handle::Top, Point { x: cx, y }),
+        (Handle::TopRight, Point { x: x + w, y }),
+        (Handle::Right, Point { x: x + w, y: cy }),
+        (Handle::BottomRight, Point { x: x + w, y: y + h }),
+        (Handle::Bottom, Point { x: cx, y: y + h }),
+        (Handle::BottomLeft, Point { x, y: y + h }),
+        (Handle::Left, Point { x, y: cy }),
+    ]
+}
+
+pub fn hit_test_handle(annotation: &Annotation, point: Point, scale: f64) -> Option<Handle> {
+    let radius = HANDLE_SIZE / scale.max(0.01);
+    annotation_handles(annotation)
+        .into_iter()
+        .find(|(_, handle)| {
+            (handle.x - point.x).abs() <= radius && (handle.y - point.y).abs() <= radius
+        })
+        .map(|(handle, _)| handle)
+}
+
+pub fn handle_at(state: &EditorState, point: Point) -> Option<Handle> {
+    let annotation = state.annotations.get(state.selected?)?;
+    let (scale, _, _) = view_transform(state);
+    hit_test_handle(annotation, point, scale)
+}
+
+pub fn reshape_annotation(annotation: &mut Annotation, handle: Handle, point: Point) {
+    if let Annotation::Callout { tip, .. } = annotation {
+        if handle == Handle::End {
+            *tip = point;
+        }
+        return;
+    }
+    if let Annotation::Line { start, end, .. } = annotation {
+        match handle {
+            Handle::Start => *start = point,
+            Handle::End => *end = point,
+            _ => {}
+        }
+        return;
+    }
+    // The magnifier's corner handles resize the inset; Start and End move the source corners.
+    if let Annotation::Magnify { source, .. } = annotation
+        && matches!(handle, Handle::Start | Handle::End)
+    {
+        let (x, y, w, h) = source.normalized();
+        *source = match handle {
+            Handle::Start => Rect {
+                x1: point.x,
+                y1: point.y,
+                x2: x + w,
+                y2: y + h,
+            },
+            _ => Rect {
+                x1: x,
+                y1: y,
+                x2: point.x,
+                y2: point.y,
+            },
+        };
+        return;
+    }
+    let bounds = match annotation {
+        Annotation::Magnify { rect, .. } => Some(*rect),
+        _ => annotation_bounds(annotation),
+    };
+    let Some(bounds) = bounds else {
+        return;
+    };
+    let (x, y, w, h) = bounds.normalized();
+    let mut target = Rect {
+        x1: x,
+        y1: y,
+        x2: x + w,
+        y2: y + h,
+    };
+    match handle {
+        Handle::TopLeft => {
+            target.x1 = point.x;
+            target.y1 = point.y;
+        }
+        Handle::Top => target.y1 = point.y,
+        Handle::TopRight => {
+            target.x2 = point.x;
+            target.y1 = point.y;
+        }
+        Handle::Right => target.x2 = point.x,
+        Handle::BottomRight => {
+            target.x2 = point.x;
+            target.y2 = point.y;
+        }
+        Handle::Bottom => target.y2 = point.y,
+        Handle::BottomLeft => {
+            target.x1 = point.x;
+            target.y2 = point.y;
+        }
+        Handle::Left => target.x1 = point.x,
+        Handle::Start | Handle::End => return,
+    }
+    // Dragging a handle past the opposite edge flips the shape; stored rects stay normalized
+    // while points are mirrored along with them.
+    let (nx, ny, nw, nh) = target.normalized();
+    let normalized = Rect {
+        x1: nx,
+        y1: ny,
+        x2: nx + nw,
+        y2: ny + nh,
+    };
+    match annotation {
+        Annotation::Rect { rect, .. }
+        | Annotation::Ellipse { rect, .. }
+        | Annotation::Magnify { rect, .. } => *rect = normalized,
+        Annotation::Blur { rect, region, .. } => {
+            if let Some(BlurRegion::Brush { points, .. } | BlurRegion::Polygon { points }) = region {
+                for point in points.iter_mut() {
+                    point.x = rescale(point.x, x, w, target.x1, target.x2);
+                    point.y = rescale(point.y, y, h, target.y1, target.y2);
+                }
+            }
+            *rect = normalized;
+        }
+        Annotation::Pen { points, .. } => {
+            for point in points.iter_mut() {
+                point.x = rescale(point.x, x, w, target.x1, target.x2);
+                point.y = rescale(point.y, y, h, target.y1, target.y2);
+            }
+        }
+        Annotation::Spotlight { holes, .. } => {
+            for hole in holes.iter_mut() {
+                hole.rect.x1 = rescale(hole.rect.x1, x, w, target.x1, target.x2);
+                hole.rect.y1 = rescale(hole.rect.y1, y, h, target.y1, target.y2);
+                hole.rect.x2 = rescale(hole.rect.x2, x, w, target.x1, target.x2);
+                hole.rect.y2 = rescale(hole.rect.y2, y, h, target.y1, target.y2);
+            }
+        }
+        Annotation::Step { center, radius, .. } => {
+            *center = Point {
+                x: nx + nw / 2.0,
+                y: ny + nh / 2.0,
+            };
+            *radius = (nw.max(nh) / 2.0).max(4.0);
+        }
+        _ => {}
+    }
+}
+
+pub fn extend_stroke(points: &mut Vec<Point>, point: Point, straight: bool) {
+    if straight {
+        points.truncate(1);
+    }
+    points.push(point);
+}
+
+fn rescale(value: f64, origin: f64, extent: f64, start: f64, end: f64) -> f64 {
+    if extent <= f64::EPSILON {
+        return value - origin + start;
+    }
+    start + (value - origin) / extent * (end - start)
+}
+
+// Dragging a callout body leaves its tail pinned to the target; the tip has its own handle.
+// Magnifiers likewise keep sampling the same source while the inset is moved.
+pub fn drag_annotation(annotation: &mut Annotation, dx: f64, dy: f64) {
+    match annotation {
+        Annotation::Callout { pos, .. } => {
+            pos.x += dx;
+            pos.y += dy;
+        }
+        Annotation::Magnify { rect, .. } => {
+            rect.x1 += dx;
+            rect.y1 += dy;
+            rect.x2 += dx;
+            rect.y2 += dy;
+        }
+        _ => move_annotation(annotation, dx, dy),
+    }
 }
 
 pub fn move_annotation(annotation: &mut Annotation, dx: f64, dy: f64) {
@@ -364,7 +1806,7 @@ pub fn move_annotation(annotation: &mut Annotation, dx: f64, dy: f64) {
                 point.y += dy;
             }
         }
-        Annotation::Rect { rect, .. } => {
+        Annotation::Rect { rect, .. } | Annotation::Ellipse { rect, .. } => {
             rect.x1 += dx;
             rect.y1 += dy;
             rect.x2 += dx;
@@ -380,11 +1822,43 @@ pub fn move_annotation(annotation: &mut Annotation, dx: f64, dy: f64) {
             pos.x += dx;
             pos.y += dy;
         }
-        Annotation::Blur { rect, .. } => {
+        Annotation::Callout { pos, tip, .. } => {
+            pos.x += dx;
+            pos.y += dy;
+            tip.x += dx;
+            tip.y += dy;
+        }
+        Annotation::Step { center, .. } => {
+            center.x += dx;
+            center.y += dy;
+        }
+        Annotation::Blur { rect, region, .. } => {
             rect.x1 += dx;
             rect.y1 += dy;
             rect.x2 += dx;
             rect.y2 += dy;
+            if let Some(BlurRegion::Brush { points, .. } | BlurRegion::Polygon { points }) = region {
+                for point in points.iter_mut() {
+                    point.x += dx;
+                    point.y += dy;
+                }
+            }
+        }
+        Annotation::Magnify { source, rect, .. } => {
+            for rect in [source, rect] {
+                rect.x1 += dx;
+                rect.y1 += dy;
+                rect.x2 += dx;
+                rect.y2 += dy;
+            }
+        }
+        Annotation::Spotlight { holes, .. } => {
+            for hole in holes.iter_mut() {
+                hole.rect.x1 += dx;
+                hole.rect.y1 += dy;
+                hole.rect.x2 += dx;
+                hole.rect.y2 += dy;
+            }
         }
     }
 }
@@ -397,23 +1871,21 @@ pub fn apply_crop(state: &mut EditorState, rect: Rect) -> bool {
     if w < 1.0 || h < 1.0 {
         return false;
     }
-    let max_w = background.width() as f64;
-    let max_h = background.height() as f64;
-    let x = x.max(0.0).min(max_w);
-    let y = y.max(0.0).min(max_h);
-    let w = w.min(max_w - x).max(1.0);
-    let h = h.min(max_h - y).max(1.0);
-    let cropped = Pixbuf::new_subpixbuf(
-        background,
-        x.round() as i32,
-        y.round() as i32,
-        w.round() as i32,
-        h.round() as i32,
-    );
-    state.background = Some(cropped);
-    for annotation in state.annotations.iter_mut() {
-        move_annotation(annotation, -x, -y);
-    }
+    // Whole pixels, computed once, so the annotations shift by exactly what was cut away.
+    let max_w = background.width();
+    let max_h = background.height();
+    let left = (x.floor() as i32).clamp(0, max_w - 1);
+    let top = (y.floor() as i32).clamp(0, max_h - 1);
+    let right = ((x + w).ceil() as i32).clamp(left + 1, max_w);
+    let bottom = ((y + h).ceil() as i32).clamp(top + 1, max_h);
+    let cropped = Pixbuf::new_subpixbuf(background, left, top, right - left, bottom - top);
+    let before = background.clone();
+    state.execute(Command::Crop {
+        before,
+        after: cropped,
+        dx: left as f64,
+        dy: top as f64,
+    });
     state.draft = None;
     state.crop_rect = None;
     state.selected = None;
@@ -453,6 +1925,79 @@ fn draw_arrow_head(
     let _ = ctx.restore();
 }
 
+pub mod rgba {
+    use gtk::gdk;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &gdk::RGBA, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&color.to_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<gdk::RGBA, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        gdk::RGBA::parse(value.as_str()).map_err(serde::de::Error::custom)
+    }
+
+    pub mod option {
+        use gtk::gdk;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            color: &Option<gdk::RGBA>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match color {
+                Some(color) => super::serialize(color, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<gdk::RGBA>, D::Error> {
+            let value = Option::<String>::deserialize(deserializer)?;
+            value
+                .map(|value| gdk::RGBA::parse(value.as_str()).map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+fn draw_handles(ctx: &cairo::Context, annotation: &Annotation, scale: f64) {
+    let size = HANDLE_SIZE / scale.max(0.01);
+    let _ = ctx.save();
+    ctx.set_line_width(1.0 / scale.max(0.01));
+    for (_, point) in annotation_handles(annotation) {
+        ctx.rectangle(point.x - size / 2.0, point.y - size / 2.0, size, size);
+        ctx.set_source_rgba(1.0, 1.0, 1.0, 0.9);
+        let _ = ctx.fill_preserve();
+        ctx.set_source_rgba(0.25, 0.45, 0.9, 0.9);
+        let _ = ctx.stroke();
+    }
+    let _ = ctx.restore();
+}
+
+fn rounded_rectangle(ctx: &cairo::Context, x: f64, y: f64, w: f64, h: f64, radius: f64) {
+    let radius = radius.min(w / 2.0).min(h / 2.0);
+    let half_pi = std::f64::consts::FRAC_PI_2;
+    ctx.new_sub_path();
+    ctx.arc(x + w - radius, y + radius, radius, -half_pi, 0.0);
+    ctx.arc(x + w - radius, y + h - radius, radius, 0.0, half_pi);
+    ctx.arc(x + radius, y + h - radius, radius, half_pi, 2.0 * half_pi);
+    ctx.arc(x + radius, y + radius, radius, 2.0 * half_pi, 3.0 * half_pi);
+    ctx.close_path();
+}
+
+fn fill_and_stroke(ctx: &cairo::Context, color: &gdk::RGBA, width: f64, fill: Option<&gdk::RGBA>) {
+    if let Some(fill) = fill {
+        set_source_rgba(ctx, fill);
+        let _ = ctx.fill_preserve();
+    }
+    set_source_rgba(ctx, color);
+    ctx.set_line_width(width);
+    let _ = ctx.stroke();
+}
+
 fn set_source_rgba(ctx: &cairo::Context, color: &gdk::RGBA) {
     ctx.set_source_rgba(
         color.red() as f64,
@@ -462,42 +2007,548 @@ fn set_source_rgba(ctx: &cairo::Context, color: &gdk::RGBA) {
     );
 }
 
-fn draw_pixelate(ctx: &cairo::Context, rect: Rect, pixel_size: i32, background: &Pixbuf) {
+fn draw_redaction(
+    ctx: &cairo::Context,
+    rect: Rect,
+    region: Option<&BlurRegion>,
+    mode: BlurMode,
+    strength: i32,
+    background: &Pixbuf,
+    cache: Option<&RenderCache>,
+) {
     let (x, y, w, h) = rect.normalized();
     if w < 1.0 || h < 1.0 {
         return;
     }
 
-    let max_w = background.width() as f64;
-    let max_h = background.height() as f64;
-    let x = x.max(0.0).min(max_w);
-    let y = y.max(0.0).min(max_h);
-    let w = w.min(max_w - x).max(1.0);
-    let h = h.min(max_h - y).max(1.0);
+    if mode == BlurMode::Solid {
+        let _ = ctx.save();
+        ctx.set_source_rgb(0.0, 0.0, 0.0);
+        paint_redaction_shape(ctx, rect, region);
+        let _ = ctx.restore();
+        return;
+    }
 
-    let sub = Pixbuf::new_subpixbuf(
-        background,
-        x.round() as i32,
-        y.round() as i32,
-        w.round() as i32,
-        h.round() as i32,
-    );
+    // Whole pixels covering the rectangle, clipped to the image; partly covered edge pixels
+    // are included so nothing under the redaction is left untouched.
+    let left = (x.floor() as i32).max(0);
+    let top = (y.floor() as i32).max(0);
+    let right = ((x + w).ceil() as i32).min(background.width());
+    let bottom = ((y + h).ceil() as i32).min(background.height());
+    if right <= left || bottom <= top {
+        return;
+    }
 
-    let small_w = (w / pixel_size as f64).max(1.0).round() as i32;
-    let small_h = (h / pixel_size as f64).max(1.0).round() as i32;
-    let small = match sub.scale_simple(small_w, small_h, gdk_pixbuf::InterpType::Nearest) {
-        Some(pix) => pix,
-        None => return,
+    let key = RedactionKey {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+        mode,
+        strength,
     };
-    let pixelated = match small.scale_simple(w.round() as i32, h.round() as i32, gdk_pixbuf::InterpType::Nearest) {
-        Some(pix) => pix,
-        None => return,
+    let build = || {
+        let sub = Pixbuf::new_subpixbuf(background, key.x, key.y, key.width, key.height);
+        match mode {
+            BlurMode::Gaussian => gaussian_blur(&sub, strength),
+            _ => pixelate(&sub, strength),
+        }
     };
+    let processed = match cache {
+        Some(cache) => cache.redaction(background, key, build),
+        None => build().and_then(|pixbuf| cache::surface_from_pixbuf(&pixbuf)),
+    };
+    let Some(processed) = processed else {
+        return;
+    };
+
+    let _ = ctx.save();
+    let _ = ctx.set_source_surface(&processed, left as f64, top as f64);
+    paint_redaction_shape(ctx, rect, region);
+    let _ = ctx.restore();
+}
+
+fn draw_magnifier(
+    ctx: &cairo::Context,
+    source: Rect,
+    rect: Rect,
+    color: &gdk::RGBA,
+    width: f64,
+    connectors: bool,
+    background: Option<&Pixbuf>,
+) {
+    let (sx, sy, sw, sh) = source.normalized();
+    let (x, y, w, h) = rect.normalized();
+    let placed = sw >= 1.0 && sh >= 1.0 && w >= 1.0 && h >= 1.0;
+    let _ = ctx.save();
+    set_source_rgba(ctx, color);
+    ctx.set_line_width(width);
+    ctx.set_line_join(cairo::LineJoin::Round);
+    ctx.set_line_cap(cairo::LineCap::Round);
+    ctx.rectangle(sx, sy, sw, sh);
+    let _ = ctx.stroke();
+
+    if placed && connectors {
+        // Join the two source corners that face the inset so the lines don't cross either box.
+        let dx = (x + w / 2.0) - (sx + sw / 2.0);
+        let dy = (y + h / 2.0) - (sy + sh / 2.0);
+        let pairs = if dx.abs() >= dy.abs() {
+            let (from, to) = if dx >= 0.0 { (sx + sw, x) } else { (sx, x + w) };
+            [((from, sy), (to, y)), ((from, sy + sh), (to, y + h))]
+        } else {
+            let (from, to) = if dy >= 0.0 { (sy + sh, y) } else { (sy, y + h) };
+            [((sx, from), (x, to)), ((sx + sw, from), (x + w, to))]
+        };
+        for ((x1, y1), (x2, y2)) in pairs {
+            ctx.move_to(x1, y1);
+            ctx.line_to(x2, y2);
+        }
+        let _ = ctx.stroke();
+    }
+    let _ = ctx.restore();
 
+    if !placed {
+        return;
+    }
+    if let Some(background) = background {
+        let max_w = background.width() as f64;
+        let max_h = background.height() as f64;
+        let left = sx.max(0.0).min(max_w - 1.0).floor();
+        let top = sy.max(0.0).min(max_h - 1.0).floor();
+        let right = (sx + sw).min(max_w).max(left + 1.0).ceil();
+        let bottom = (sy + sh).min(max_h).max(top + 1.0).ceil();
+        let sub = Pixbuf::new_subpixbuf(
+            background,
+            left as i32,
+            top as i32,
+            (right - left) as i32,
+            (bottom - top) as i32,
+        );
+        let _ = ctx.save();
+        ctx.rectangle(x, y, w, h);
+        ctx.clip();
+        ctx.translate(x, y);
+        ctx.scale(w / sw, h / sh);
+        ctx.set_source_pixbuf(&sub, left - sx, top - sy);
+        // Nearest-neighbour keeps enlarged pixels crisp, which is the point of zooming in on UI.
+        let pattern = ctx.source();
+        pattern.set_filter(cairo::Filter::Nearest);
+        pattern.set_extend(cairo::Extend::Pad);
+        let _ = ctx.paint();
+        let _ = ctx.restore();
+    }
     let _ = ctx.save();
+    set_source_rgba(ctx, color);
+    ctx.set_line_width(width);
+    ctx.set_line_join(cairo::LineJoin::Round);
     ctx.rectangle(x, y, w, h);
-    let _ = ctx.clip();
-    ctx.set_source_pixbuf(&pixelated, x, y);
-    let _ = ctx.paint();
+    let _ = ctx.stroke();
     let _ = ctx.restore();
 }
+
+fn pixelate(pixbuf: &Pixbuf, pixel_size: i32) -> Option<Pixbuf> {
+    let w = pixbuf.width();
+    let h = pixbuf.height();
+    let pixel_size = pixel_size.max(1) as f64;
+    let small_w = (w as f64 / pixel_size).max(1.0).round() as i32;
+    let small_h = (h as f64 / pixel_size).max(1.0).round() as i32;
+    let small = pixbuf.scale_simple(small_w, small_h, gdk_pixbuf::InterpType::Nearest)?;
+    small.scale_simple(w, h, gdk_pixbuf::InterpType::Nearest)
+}
+
+fn gaussian_blur(pixbuf: &Pixbuf, radius: i32) -> Option<Pixbuf> {
+    let pixbuf = pixbuf.copy()?;
+    let layout = PixelLayout {
+        width: pixbuf.width() as usize,
+        height: pixbuf.height() as usize,
+        channels: pixbuf.n_channels() as usize,
+        rowstride: pixbuf.rowstride() as usize,
+    };
+    let radius = (radius.max(1) as usize).min(layout.width.max(layout.height));
+    let mut data = pixbuf.read_pixel_bytes().to_vec();
+    let mut scratch = data.clone();
+    // Three box blur passes approximate a gaussian closely enough for redaction.
+    for _ in 0..3 {
+        box_blur(&data, &mut scratch, &layout, radius, true);
+        box_blur(&scratch, &mut data, &layout, radius, false);
+    }
+    Some(Pixbuf::from_bytes(
+        &glib::Bytes::from_owned(data),
+        gdk_pixbuf::Colorspace::Rgb,
+        pixbuf.has_alpha(),
+        8,
+        layout.width as i32,
+        layout.height as i32,
+        layout.rowstride as i32,
+    ))
+}
+
+struct PixelLayout {
+    width: usize,
+    height: usize,
+    channels: usize,
+    rowstride: usize,
+}
+
+fn box_blur(src: &[u8], dst: &mut [u8], layout: &PixelLayout, radius: usize, horizontal: bool) {
+    let (lines, len) = if horizontal {
+        (layout.height, layout.width)
+    } else {
+        (layout.width, layout.height)
+    };
+    let offset = |line: usize, pos: usize| {
+        if horizontal {
+            line * layout.rowstride + pos * layout.channels
+        } else {
+            pos * layout.rowstride + line * layout.channels
+        }
+    };
+    let window = (2 * radius + 1) as u32;
+    for line in 0..lines {
+        for channel in 0..layout.channels {
+            let mut sum: u32 = (0..=2 * radius)
+                .map(|i| src[offset(line, i.saturating_sub(radius).min(len - 1)) + channel] as u32)
+                .sum();
+            for pos in 0..len {
+                dst[offset(line, pos) + channel] = (sum / window) as u8;
+                let next = (pos + radius + 1).min(len - 1);
+                let prev = pos.saturating_sub(radius);
+                sum += src[offset(line, next) + channel] as u32;
+                sum -= src[offset(line, prev) + channel] as u32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gdk_pixbuf::Colorspace;
+
+    use super::*;
+
+    const WIDTH: i32 = 200;
+    const HEIGHT: i32 = 100;
+
+    // Returns the canvas before and after drawing the redaction over a checkerboard.
+    fn redact(rect: Rect, mode: BlurMode) -> (Vec<u8>, Vec<u8>) {
+        let background = Pixbuf::new(Colorspace::Rgb, true, 8, WIDTH, HEIGHT).unwrap();
+        for y in 0..HEIGHT as u32 {
+            for x in 0..WIDTH as u32 {
+                let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+                background.put_pixel(x, y, value, value, value, 255);
+            }
+        }
+        let mut surface =
+            cairo::ImageSurface::create(cairo::Format::ARgb32, WIDTH, HEIGHT).unwrap();
+        let ctx = cairo::Context::new(&surface).unwrap();
+        ctx.set_source_pixbuf(&background, 0.0, 0.0);
+        ctx.paint().unwrap();
+        drop(ctx);
+        surface.flush();
+        let before = surface.data().unwrap().to_vec();
+
+        let ctx = cairo::Context::new(&surface).unwrap();
+        draw_redaction(&ctx, rect, None, mode, 8, &background, None);
+        drop(ctx);
+        surface.flush();
+        let after = surface.data().unwrap().to_vec();
+        (before, after)
+    }
+
+    fn pixel(data: &[u8], x: i32, y: i32) -> &[u8] {
+        let stride = cairo::Format::ARgb32.stride_for_width(WIDTH as u32).unwrap() as usize;
+        let offset = y as usize * stride + x as usize * 4;
+        &data[offset..offset + 4]
+    }
+
+    #[test]
+    fn redaction_at_fractional_edges_stays_inside_the_image() {
+        let rect = Rect {
+            x1: 100.5,
+            y1: 10.5,
+            x2: 200.0,
+            y2: 100.0,
+        };
+        for mode in [BlurMode::Pixelate, BlurMode::Gaussian] {
+            let (before, after) = redact(rect, mode);
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    if x >= 101 && y >= 11 {
+                        assert_ne!(pixel(&before, x, y), pixel(&after, x, y), "{mode:?} at {x},{y}");
+                    } else if x < 100 || y < 10 {
+                        assert_eq!(pixel(&before, x, y), pixel(&after, x, y), "{mode:?} at {x},{y}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn redaction_past_the_image_is_skipped() {
+        let right = Rect {
+            x1: 250.0,
+            y1: 10.0,
+            x2: 300.0,
+            y2: 40.0,
+        };
+        let (before, after) = redact(right, BlurMode::Pixelate);
+        assert!(before == after);
+        let above = Rect {
+            x1: 10.0,
+            y1: -60.0,
+            x2: 40.0,
+            y2: -20.0,
+        };
+        let (before, after) = redact(above, BlurMode::Gaussian);
+        assert!(before == after);
+    }
+
+    fn red_rect(x1: f64, y1: f64, x2: f64, y2: f64) -> Annotation {
+        Annotation::Rect {
+            rect: Rect { x1, y1, x2, y2 },
+            color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+            width: 2.0,
+            fill: None,
+        }
+    }
+
+    fn rect_of(annotation: &Annotation) -> (f64, f64, f64, f64) {
+        match annotation {
+            Annotation::Rect { rect, .. } => (rect.x1, rect.y1, rect.x2, rect.y2),
+            other => panic!("expected a rect, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn alt_drag_copy_is_undone_in_one_step() {
+        let mut state = EditorState::new();
+        state.push_annotation(red_rect(0.0, 0.0, 10.0, 10.0));
+        state.selected = Some(0);
+        assert!(state.begin_duplicate_drag());
+        assert_eq!(state.selected, Some(1));
+
+        // What the drag does to the copy, followed by what ending the drag records.
+        move_annotation(&mut state.annotations[1], 20.0, 5.0);
+        let annotation = state.annotations[1].clone();
+        state.record(Command::Add { index: 1, annotation });
+
+        state.undo();
+        assert_eq!(state.annotations.len(), 1);
+        assert_eq!(rect_of(&state.annotations[0]), (0.0, 0.0, 10.0, 10.0));
+        state.undo();
+        assert!(state.annotations.is_empty());
+        state.redo();
+        state.redo();
+        assert_eq!(rect_of(&state.annotations[1]), (20.0, 5.0, 30.0, 15.0));
+    }
+
+    fn points_of(annotation: &Annotation) -> Vec<(f64, f64)> {
+        let points = match annotation {
+            Annotation::Pen { points, .. } => points,
+            Annotation::Blur {
+                region: Some(BlurRegion::Brush { points, .. }),
+                ..
+            } => points,
+            other => panic!("expected points, got {other:?}"),
+        };
+        points.iter().map(|point| (point.x, point.y)).collect()
+    }
+
+    #[test]
+    fn handles_dragged_past_the_opposite_edge_keep_rects_normalized() {
+        let cases = [
+            (Handle::TopLeft, (40.0, 30.0), (30.0, 20.0, 40.0, 30.0)),
+            (Handle::Top, (0.0, 30.0), (10.0, 20.0, 30.0, 30.0)),
+            (Handle::TopRight, (0.0, 30.0), (0.0, 20.0, 10.0, 30.0)),
+            (Handle::Right, (0.0, 0.0), (0.0, 10.0, 10.0, 20.0)),
+            (Handle::BottomRight, (0.0, 0.0), (0.0, 0.0, 10.0, 10.0)),
+            (Handle::Bottom, (0.0, 0.0), (10.0, 0.0, 30.0, 10.0)),
+            (Handle::BottomLeft, (40.0, 0.0), (30.0, 0.0, 40.0, 10.0)),
+            (Handle::Left, (40.0, 0.0), (30.0, 10.0, 40.0, 20.0)),
+        ];
+        for (handle, (x, y), expected) in cases {
+            let mut annotation = red_rect(10.0, 10.0, 30.0, 20.0);
+            reshape_annotation(&mut annotation, handle, Point { x, y });
+            assert_eq!(rect_of(&annotation), expected, "{handle:?}");
+            let (corner, point) = annotation_handles(&annotation)[0];
+            assert_eq!(corner, Handle::TopLeft);
+            assert_eq!((point.x, point.y), (expected.0, expected.1), "{handle:?}");
+        }
+    }
+
+    #[test]
+    fn resizing_scales_pen_strokes_and_brush_regions() {
+        let mut pen = Annotation::Pen {
+            points: vec![
+                Point { x: 0.0, y: 0.0 },
+                Point { x: 10.0, y: 10.0 },
+                Point { x: 20.0, y: 5.0 },
+            ],
+            color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+            width: 4.0,
+            highlighter: false,
+        };
+        reshape_annotation(&mut pen, Handle::BottomRight, Point { x: 40.0, y: 30.0 });
+        assert_eq!(
+            points_of(&pen),
+            [(0.0, 0.0), (20.0, 30.0), (40.0, 15.0)]
+        );
+
+        let mut brush = Annotation::Blur {
+            rect: Rect {
+                x1: 0.0,
+                y1: 0.0,
+                x2: 20.0,
+                y2: 10.0,
+            },
+            mode: BlurMode::Pixelate,
+            strength: 8,
+            region: Some(BlurRegion::Brush {
+                points: vec![Point { x: 0.0, y: 0.0 }, Point { x: 20.0, y: 10.0 }],
+                width: 4.0,
+            }),
+            include_below: false,
+        };
+        reshape_annotation(&mut brush, Handle::Right, Point { x: 40.0, y: 5.0 });
+        assert_eq!(points_of(&brush), [(0.0, 0.0), (40.0, 10.0)]);
+        let Annotation::Blur { rect, .. } = &brush else {
+            unreachable!();
+        };
+        assert_eq!((rect.x1, rect.y1, rect.x2, rect.y2), (0.0, 0.0, 40.0, 10.0));
+    }
+
+    #[test]
+    fn hollow_and_filled_shapes_are_hit_inside_their_outline() {
+        let fill = Some(gdk::RGBA::new(0.0, 0.0, 1.0, 0.5));
+        for fill in [None, fill] {
+            let rect = Annotation::Rect {
+                rect: Rect {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 40.0,
+                    y2: 20.0,
+                },
+                color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+                width: 2.0,
+                fill,
+            };
+            let ellipse = Annotation::Ellipse {
+                rect: Rect {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 40.0,
+                    y2: 20.0,
+                },
+                color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+                width: 2.0,
+                fill,
+            };
+            let center = Point { x: 20.0, y: 10.0 };
+            let corner = Point { x: 1.0, y: 1.0 };
+            let outside = Point { x: 50.0, y: 10.0 };
+            assert_eq!(hit_test(std::slice::from_ref(&rect), center), Some(0));
+            assert_eq!(hit_test(std::slice::from_ref(&rect), corner), Some(0));
+            assert_eq!(hit_test(std::slice::from_ref(&rect), outside), None);
+            assert_eq!(hit_test(std::slice::from_ref(&ellipse), center), Some(0));
+            assert_eq!(hit_test(std::slice::from_ref(&ellipse), corner), None);
+            assert_eq!(hit_test(std::slice::from_ref(&ellipse), outside), None);
+        }
+    }
+
+    fn step_at(x: f64) -> Annotation {
+        Annotation::Step {
+            center: Point { x, y: 10.0 },
+            radius: 8.0,
+            color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    // Each step's x position paired with the number it's drawn with.
+    fn step_numbers(state: &EditorState) -> Vec<(f64, usize)> {
+        state
+            .annotations
+            .iter()
+            .enumerate()
+            .filter_map(|(index, annotation)| match annotation {
+                Annotation::Step { center, .. } => {
+                    Some((center.x, step_number(&state.annotations, index)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn step_numbers_follow_deletes_and_reorders() {
+        let mut state = EditorState::new();
+        state.push_annotation(step_at(10.0));
+        state.push_annotation(red_rect(0.0, 0.0, 5.0, 5.0));
+        state.push_annotation(step_at(20.0));
+        state.push_annotation(step_at(30.0));
+        assert_eq!(step_numbers(&state), [(10.0, 1), (20.0, 2), (30.0, 3)]);
+
+        state.selected = Some(0);
+        assert!(state.delete_selected());
+        assert_eq!(step_numbers(&state), [(20.0, 1), (30.0, 2)]);
+
+        state.selected = Some(2);
+        assert!(state.reorder_selected(ZOrder::Back));
+        assert_eq!(step_numbers(&state), [(30.0, 1), (20.0, 2)]);
+
+        state.undo();
+        state.undo();
+        assert_eq!(step_numbers(&state), [(10.0, 1), (20.0, 2), (30.0, 3)]);
+    }
+
+    fn spotlight_holes(state: &EditorState) -> Vec<(f64, f64, f64, f64)> {
+        state
+            .annotations
+            .iter()
+            .filter_map(|annotation| match annotation {
+                Annotation::Spotlight { holes, .. } => Some(holes),
+                _ => None,
+            })
+            .flatten()
+            .map(|hole| (hole.rect.x1, hole.rect.y1, hole.rect.x2, hole.rect.y2))
+            .collect()
+    }
+
+    #[test]
+    fn spotlight_holes_are_added_to_one_spotlight() {
+        let mut state = EditorState::new();
+        state.begin_spotlight(Point { x: 0.0, y: 0.0 });
+        assert!(state.draft.is_some());
+        state.finish_spotlight(Point { x: 10.0, y: 10.0 });
+        assert_eq!(state.annotations.len(), 1);
+
+        state.begin_spotlight(Point { x: 20.0, y: 20.0 });
+        state.extend_spotlight(Point { x: 25.0, y: 25.0 });
+        state.finish_spotlight(Point { x: 30.0, y: 40.0 });
+        assert_eq!(state.annotations.len(), 1);
+        assert_eq!(
+            spotlight_holes(&state),
+            [(0.0, 0.0, 10.0, 10.0), (20.0, 20.0, 30.0, 40.0)]
+        );
+
+        // A click without a drag doesn't leave an empty hole or a history entry behind.
+        state.begin_spotlight(Point { x: 50.0, y: 50.0 });
+        state.finish_spotlight(Point { x: 50.5, y: 50.5 });
+        assert_eq!(spotlight_holes(&state).len(), 2);
+
+        state.undo();
+        assert_eq!(spotlight_holes(&state), [(0.0, 0.0, 10.0, 10.0)]);
+        state.undo();
+        assert!(state.annotations.is_empty());
+    }
+
+    #[test]
+    fn clicks_in_a_spotlight_hole_pick_what_it_frames() {
+        let mut state = EditorState::new();
+        state.push_annotation(red_rect(10.0, 10.0, 20.0, 20.0));
+        state.begin_spotlight(Point { x: 0.0, y: 0.0 });
+        state.finish_spotlight(Point { x: 50.0, y: 50.0 });
+        assert_eq!(hit_test(&state.annotations, Point { x: 15.0, y: 15.0 }), Some(0));
+        assert_eq!(hit_test(&state.annotations, Point { x: 40.0, y: 40.0 }), Some(1));
+        assert_eq!(hit_test(&state.annotations, Point { x: 70.0, y: 70.0 }), None);
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use gtk::gdk;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+    Bmp,
+    Tiff,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 6] = [
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::Webp,
+        ImageFormat::Avif,
+        ImageFormat::Bmp,
+        ImageFormat::Tiff,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Webp => "WebP",
+            ImageFormat::Avif => "AVIF",
+            ImageFormat::Bmp => "BMP",
+            ImageFormat::Tiff => "TIFF",
+        }
+    }
+
+    // The first extension is the one appended to names typed without one.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Png => &["png"],
+            ImageFormat::Jpeg => &["jpg", "jpeg"],
+            ImageFormat::Webp => &["webp"],
+            ImageFormat::Avif => &["avif"],
+            ImageFormat::Bmp => &["bmp"],
+            ImageFormat::Tiff => &["tif", "tiff"],
+        }
+    }
+
+    fn pixbuf_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Tiff => "tiff",
+        }
+    }
+
+    pub fn has_alpha(self) -> bool {
+        !matches!(self, ImageFormat::Jpeg | ImageFormat::Bmp)
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    // WebP and AVIF rely on optional gdk-pixbuf loaders, which may not be installed.
+    pub fn is_writable(self) -> bool {
+        Pixbuf::formats().iter().any(|format| {
+            format.is_writable() && format.name().as_deref() == Some(self.pixbuf_type())
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SaveOptions {
+    // 1-100, used by JPEG, WebP and AVIF.
+    pub quality: u32,
+    // 0-9, PNG only.
+    pub png_compression: u32,
+    // Formats without an alpha channel are flattened onto this color.
+    pub background: gdk::RGBA,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            quality: 90,
+            png_compression: 6,
+            background: gdk::RGBA::WHITE,
+        }
+    }
+}
+
+// A path without an extension gets the fallback format's; an unknown extension is an error
+// rather than silently writing data that doesn't match the file name.
+pub fn resolve_path(
+    mut path: PathBuf,
+    fallback: ImageFormat,
+) -> Result<(PathBuf, ImageFormat), String> {
+    if let Some(format) = ImageFormat::from_path(&path) {
+        return Ok((path, format));
+    }
+    match path.extension() {
+        Some(extension) => Err(format!(
+            "Unsupported image format: .{}",
+            extension.to_string_lossy()
+        )),
+        None => {
+            path.set_extension(fallback.extensions()[0]);
+            Ok((path, fallback))
+        }
+    }
+}
+
+pub fn save(
+    pixbuf: &Pixbuf,
+    path: &Path,
+    format: ImageFormat,
+    options: &SaveOptions,
+) -> Result<(), String> {
+    if !format.is_writable() {
+        return Err(format!(
+            "Saving {} isn't supported by the installed image loaders.",
+            format.label()
+        ));
+    }
+    let flattened;
+    let pixbuf = if pixbuf.has_alpha() && !format.has_alpha() {
+        flattened = flatten(pixbuf, &options.background)
+            .ok_or_else(|| "Failed to flatten image.".to_string())?;
+        &flattened
+    } else {
+        pixbuf
+    };
+    let quality = options.quality.clamp(1, 100).to_string();
+    let compression = options.png_compression.min(9).to_string();
+    let params: Vec<(&str, &str)> = match format {
+        ImageFormat::Png => vec![("compression", &compression)],
+        ImageFormat::Jpeg | ImageFormat::Webp | ImageFormat::Avif => vec![("quality", &quality)],
+        // libtiff codec 5 is LZW: lossless and readable everywhere.
+        ImageFormat::Tiff => vec![("compression", "5")],
+        ImageFormat::Bmp => Vec::new(),
+    };
+    pixbuf
+        .savev(path, format.pixbuf_type(), &params)
+        .map_err(|err| format!("Failed to save {}: {err}", path.display()))
+}
+
+pub fn flatten(pixbuf: &Pixbuf, background: &gdk::RGBA) -> Option<Pixbuf> {
+    let (width, height) = (pixbuf.width(), pixbuf.height());
+    let flat = Pixbuf::new(Colorspace::Rgb, false, 8, width, height)?;
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u32;
+    flat.fill(
+        (channel(background.red()) << 24)
+            | (channel(background.green()) << 16)
+            | (channel(background.blue()) << 8)
+            | 0xff,
+    );
+    pixbuf.composite(
+        &flat,
+        0,
+        0,
+        width,
+        height,
+        0.0,
+        0.0,
+        1.0,
+        1.0,
+        InterpType::Nearest,
+        255,
+    );
+    Some(flat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_the_format_from_the_extension() {
+        let (path, format) = resolve_path(PathBuf::from("shot.JPEG"), ImageFormat::Png).unwrap();
+        assert_eq!(
+            (path, format),
+            (PathBuf::from("shot.JPEG"), ImageFormat::Jpeg)
+        );
+        let (_, format) = resolve_path(PathBuf::from("shot.Tif"), ImageFormat::Png).unwrap();
+        assert_eq!(format, ImageFormat::Tiff);
+    }
+
+    #[test]
+    fn appends_the_fallback_extension_when_missing() {
+        let (path, format) = resolve_path(PathBuf::from("dir/shot"), ImageFormat::Webp).unwrap();
+        assert_eq!(
+            (path, format),
+            (PathBuf::from("dir/shot.webp"), ImageFormat::Webp)
+        );
+        let (path, _) = resolve_path(PathBuf::from("shot"), ImageFormat::Jpeg).unwrap();
+        assert_eq!(path, PathBuf::from("shot.jpg"));
+    }
+
+    #[test]
+    fn rejects_unknown_extensions() {
+        let err = resolve_path(PathBuf::from("shot.xcf"), ImageFormat::Png)
+            .err()
+            .unwrap();
+        assert_eq!(err, "Unsupported image format: .xcf");
+    }
+
+    #[test]
+    fn flatten_composites_alpha_onto_the_background() {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, true, 8, 3, 1).unwrap();
+        pixbuf.put_pixel(0, 0, 255, 0, 0, 255);
+        pixbuf.put_pixel(1, 0, 255, 0, 0, 0);
+        pixbuf.put_pixel(2, 0, 0, 0, 255, 128);
+        let green = gdk::RGBA::new(0.0, 1.0, 0.0, 1.0);
+        let flat = flatten(&pixbuf, &green).unwrap();
+        assert!(!flat.has_alpha());
+
+        let bytes = flat.read_pixel_bytes();
+        assert_eq!(&bytes[0..3], &[255, 0, 0]);
+        assert_eq!(&bytes[3..6], &[0, 255, 0]);
+        let blended = &bytes[6..9];
+        assert_eq!(blended[0], 0);
+        assert!(blended[1].abs_diff(127) <= 1, "{blended:?}");
+        assert!(blended[2].abs_diff(128) <= 1, "{blended:?}");
+    }
+}
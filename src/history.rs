@@ -0,0 +1,276 @@
+use gdk_pixbuf::Pixbuf;
+
+use crate::editor::{self, Annotation, EditorState};
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Add {
+        index: usize,
+        annotation: Annotation,
+    },
+    Remove {
+        index: usize,
+        annotation: Annotation,
+    },
+    Move {
+        index: usize,
+        dx: f64,
+        dy: f64,
+    },
+    Modify {
+        index: usize,
+        before: Annotation,
+        after: Annotation,
+    },
+    Reorder {
+        from: usize,
+        to: usize,
+    },
+    Crop {
+        before: Pixbuf,
+        after: Pixbuf,
+        dx: f64,
+        dy: f64,
+    },
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+}
+
+impl History {
+    pub fn push(&mut self, command: Command) {
+        self.undo.push(command);
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Command> {
+        self.undo.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Command> {
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, command: Command) {
+        self.undo.push(command);
+    }
+
+    pub fn push_redo(&mut self, command: Command) {
+        self.redo.push(command);
+    }
+}
+
+pub fn apply(state: &mut EditorState, command: &Command) {
+    match command {
+        Command::Add { index, annotation } => {
+            let index = (*index).min(state.annotations.len());
+            state.annotations.insert(index, annotation.clone());
+        }
+        Command::Remove { index, .. } => {
+            if *index < state.annotations.len() {
+                state.annotations.remove(*index);
+            }
+        }
+        Command::Move { index, dx, dy } => {
+            if let Some(annotation) = state.annotations.get_mut(*index) {
+                editor::move_annotation(annotation, *dx, *dy);
+            }
+        }
+        Command::Modify { index, after, .. } => {
+            if let Some(annotation) = state.annotations.get_mut(*index) {
+                *annotation = after.clone();
+            }
+        }
+        Command::Reorder { from, to } => reorder(state, *from, *to),
+        Command::Crop { after, dx, dy, .. } => {
+            state.background = Some(after.clone());
+            for annotation in state.annotations.iter_mut() {
+                editor::move_annotation(annotation, -dx, -dy);
+            }
+        }
+    }
+}
+
+pub fn revert(state: &mut EditorState, command: &Command) {
+    match command {
+        Command::Add { index, .. } => {
+            if *index < state.annotations.len() {
+                state.annotations.remove(*index);
+            }
+        }
+        Command::Remove { index, annotation } => {
+            let index = (*index).min(state.annotations.len());
+            state.annotations.insert(index, annotation.clone());
+        }
+        Command::Move { index, dx, dy } => {
+            if let Some(annotation) = state.annotations.get_mut(*index) {
+                editor::move_annotation(annotation, -dx, -dy);
+            }
+        }
+        Command::Modify { index, before, .. } => {
+            if let Some(annotation) = state.annotations.get_mut(*index) {
+                *annotation = before.clone();
+            }
+        }
+        Command::Reorder { from, to } => reorder(state, *to, *from),
+        Command::Crop { before, dx, dy, .. } => {
+            state.background = Some(before.clone());
+            for annotation in state.annotations.iter_mut() {
+                editor::move_annotation(annotation, *dx, *dy);
+            }
+        }
+    }
+}
+
+fn reorder(state: &mut EditorState, from: usize, to: usize) {
+    if from < state.annotations.len() && to < state.annotations.len() {
+        let annotation = state.annotations.remove(from);
+        state.annotations.insert(to, annotation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gdk_pixbuf::Colorspace;
+    use gtk::gdk;
+
+    use super::*;
+    use crate::editor::{Point, Rect};
+
+    fn line(x: f64, y: f64) -> Annotation {
+        Annotation::Line {
+            start: Point { x, y },
+            end: Point {
+                x: x + 10.0,
+                y: y + 10.0,
+            },
+            color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+            width: 2.0,
+            arrow: false,
+        }
+    }
+
+    fn start(annotation: &Annotation) -> (f64, f64) {
+        match annotation {
+            Annotation::Line { start, .. } => (start.x, start.y),
+            _ => panic!("expected a line"),
+        }
+    }
+
+    fn starts(state: &EditorState) -> Vec<(f64, f64)> {
+        state.annotations.iter().map(start).collect()
+    }
+
+    fn state_with_lines() -> EditorState {
+        let mut state = EditorState::new();
+        state.annotations = vec![line(0.0, 0.0), line(20.0, 20.0), line(40.0, 40.0)];
+        state
+    }
+
+    #[test]
+    fn add_and_remove_revert_at_their_index() {
+        let mut state = state_with_lines();
+        let add = Command::Add {
+            index: 1,
+            annotation: line(5.0, 5.0),
+        };
+        apply(&mut state, &add);
+        assert_eq!(starts(&state), [(0.0, 0.0), (5.0, 5.0), (20.0, 20.0), (40.0, 40.0)]);
+        revert(&mut state, &add);
+        assert_eq!(starts(&state), [(0.0, 0.0), (20.0, 20.0), (40.0, 40.0)]);
+
+        let remove = Command::Remove {
+            index: 0,
+            annotation: line(0.0, 0.0),
+        };
+        apply(&mut state, &remove);
+        assert_eq!(starts(&state), [(20.0, 20.0), (40.0, 40.0)]);
+        revert(&mut state, &remove);
+        assert_eq!(starts(&state), [(0.0, 0.0), (20.0, 20.0), (40.0, 40.0)]);
+    }
+
+    #[test]
+    fn move_and_modify_revert() {
+        let mut state = state_with_lines();
+        let moved = Command::Move {
+            index: 2,
+            dx: 3.0,
+            dy: -4.0,
+        };
+        apply(&mut state, &moved);
+        assert_eq!(start(&state.annotations[2]), (43.0, 36.0));
+        revert(&mut state, &moved);
+        assert_eq!(start(&state.annotations[2]), (40.0, 40.0));
+
+        let modify = Command::Modify {
+            index: 1,
+            before: line(20.0, 20.0),
+            after: line(70.0, 80.0),
+        };
+        apply(&mut state, &modify);
+        assert_eq!(start(&state.annotations[1]), (70.0, 80.0));
+        revert(&mut state, &modify);
+        assert_eq!(start(&state.annotations[1]), (20.0, 20.0));
+    }
+
+    #[test]
+    fn reorder_reverts() {
+        let mut state = state_with_lines();
+        let command = Command::Reorder { from: 0, to: 2 };
+        apply(&mut state, &command);
+        assert_eq!(starts(&state), [(20.0, 20.0), (40.0, 40.0), (0.0, 0.0)]);
+        revert(&mut state, &command);
+        assert_eq!(starts(&state), [(0.0, 0.0), (20.0, 20.0), (40.0, 40.0)]);
+    }
+
+    #[test]
+    fn crop_shifts_annotations_by_whole_pixels() {
+        let mut state = EditorState::new();
+        let background = Pixbuf::new(Colorspace::Rgb, true, 8, 100, 80).unwrap();
+        state.set_background(background);
+        state.annotations = vec![line(20.0, 20.0)];
+
+        let crop = Rect {
+            x1: 10.6,
+            y1: 5.4,
+            x2: 60.2,
+            y2: 50.5,
+        };
+        assert!(editor::apply_crop(&mut state, crop));
+        let cropped = state.background.clone().unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (51, 46));
+        assert_eq!(start(&state.annotations[0]), (10.0, 15.0));
+
+        state.undo();
+        let restored = state.background.clone().unwrap();
+        assert_eq!((restored.width(), restored.height()), (100, 80));
+        assert_eq!(start(&state.annotations[0]), (20.0, 20.0));
+
+        state.redo();
+        assert_eq!(state.background.as_ref().unwrap().width(), 51);
+        assert_eq!(start(&state.annotations[0]), (10.0, 15.0));
+    }
+
+    #[test]
+    fn crop_past_the_edge_stays_inside_the_image() {
+        let mut state = EditorState::new();
+        state.set_background(Pixbuf::new(Colorspace::Rgb, true, 8, 40, 30).unwrap());
+        let crop = Rect {
+            x1: 35.5,
+            y1: 25.5,
+            x2: 90.0,
+            y2: 70.0,
+        };
+        assert!(editor::apply_crop(&mut state, crop));
+        let cropped = state.background.clone().unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (5, 5));
+    }
+}
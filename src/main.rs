@@ -1,12 +1,70 @@
+use std::cell::OnceCell;
+use std::rc::Rc;
+
 mod ui;
+mod cache;
+mod cli;
+mod config;
 mod editor;
+mod export;
+mod history;
+mod palette;
+mod portal;
+mod preferences;
+mod project;
+mod redact;
+mod render;
+mod shortcuts;
 
 const APP_ID: &str = "io.github.syed.greatshot";
 
-fn main() {
+fn main() -> gtk::glib::ExitCode {
     use adw::prelude::*;
+    use gtk::gio;
+    use gtk::glib;
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("render") {
+        if let Err(err) = render::run(&args[2..]) {
+            eprintln!("greatshot render: {err}");
+            return glib::ExitCode::FAILURE;
+        }
+        return glib::ExitCode::SUCCESS;
+    }
+
+    let app = adw::Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_COMMAND_LINE | gio::ApplicationFlags::HANDLES_OPEN)
+        .build();
+    cli::add_options(&app);
 
-    let app = adw::Application::builder().application_id(APP_ID).build();
-    app.connect_activate(ui::build_ui);
-    app.run();
+    let editor: Rc<OnceCell<ui::EditorWindow>> = Rc::new(OnceCell::new());
+    {
+        let editor = editor.clone();
+        app.connect_activate(move |app| {
+            editor.get_or_init(|| ui::build_ui(app)).present();
+        });
+    }
+    {
+        let editor = editor.clone();
+        app.connect_open(move |app, files, _| {
+            let editor = editor.get_or_init(|| ui::build_ui(app));
+            if let Some(file) = files.first() {
+                editor.open(file);
+            }
+            editor.present();
+        });
+    }
+    {
+        let editor = editor.clone();
+        app.connect_command_line(move |app, command_line| {
+            let editor = editor.get_or_init(|| ui::build_ui(app));
+            cli::handle_command_line(app, editor, command_line)
+        });
+    }
+    let status = app.run();
+    if editor.get().is_some_and(ui::EditorWindow::capture_failed) {
+        return glib::ExitCode::FAILURE;
+    }
+    status
 }
@@ -0,0 +1,299 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gtk::gdk;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+const PALETTE_FILE: &str = "palettes.json";
+const MAX_RECENT: usize = 8;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Swatch {
+    #[serde(with = "crate::editor::rgba")]
+    pub color: gdk::RGBA,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Palette {
+    pub name: String,
+    pub swatches: Vec<Swatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteStore {
+    pub palettes: Vec<Palette>,
+    pub active: usize,
+    #[serde(with = "rgba_list")]
+    pub recent: Vec<gdk::RGBA>,
+}
+
+impl Default for PaletteStore {
+    fn default() -> Self {
+        Self {
+            palettes: vec![default_palette()],
+            active: 0,
+            recent: Vec::new(),
+        }
+    }
+}
+
+impl PaletteStore {
+    pub fn active_palette(&self) -> Option<&Palette> {
+        self.palettes.get(self.active)
+    }
+
+    pub fn active_palette_mut(&mut self) -> Option<&mut Palette> {
+        self.palettes.get_mut(self.active)
+    }
+
+    pub fn push_recent(&mut self, color: gdk::RGBA) {
+        self.recent.retain(|recent| !same_color(recent, &color));
+        self.recent.insert(0, color);
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    pub fn add_palette(&mut self, palette: Palette) {
+        self.palettes.push(palette);
+        self.active = self.palettes.len() - 1;
+    }
+
+    // The last palette can't be removed, so there is always something to pick from.
+    pub fn remove_active_palette(&mut self) -> bool {
+        if self.palettes.len() <= 1 || self.active >= self.palettes.len() {
+            return false;
+        }
+        self.palettes.remove(self.active);
+        self.active = self.active.min(self.palettes.len() - 1);
+        true
+    }
+}
+
+pub fn default_palette() -> Palette {
+    let colors = [
+        ("Black", gdk::RGBA::new(0.11, 0.11, 0.11, 1.0)),
+        ("White", gdk::RGBA::new(0.96, 0.96, 0.96, 1.0)),
+        ("Red", gdk::RGBA::new(1.0, 0.30, 0.30, 1.0)),
+        ("Orange", gdk::RGBA::new(1.0, 0.62, 0.10, 1.0)),
+        ("Yellow", gdk::RGBA::new(1.0, 0.85, 0.24, 1.0)),
+        ("Green", gdk::RGBA::new(0.24, 0.86, 0.52, 1.0)),
+        ("Blue", gdk::RGBA::new(0.23, 0.51, 0.96, 1.0)),
+        ("Purple", gdk::RGBA::new(0.55, 0.36, 0.96, 1.0)),
+    ];
+    Palette {
+        name: "Default".to_string(),
+        swatches: colors
+            .into_iter()
+            .map(|(name, color)| Swatch {
+                color,
+                name: name.to_string(),
+            })
+            .collect(),
+    }
+}
+
+pub fn same_color(a: &gdk::RGBA, b: &gdk::RGBA) -> bool {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    channel(a.red()) == channel(b.red())
+        && channel(a.green()) == channel(b.green())
+        && channel(a.blue()) == channel(b.blue())
+        && channel(a.alpha()) == channel(b.alpha())
+}
+
+fn store_path() -> PathBuf {
+    config::dir().join(PALETTE_FILE)
+}
+
+pub fn load() -> Result<PaletteStore, String> {
+    let Some(mut store) = config::load_json::<PaletteStore>(&store_path())? else {
+        return Ok(PaletteStore::default());
+    };
+    if store.palettes.is_empty() {
+        store.palettes.push(default_palette());
+    }
+    store.active = store.active.min(store.palettes.len() - 1);
+    Ok(store)
+}
+
+pub fn save(store: &PaletteStore) -> Result<(), String> {
+    let path = store_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("Failed to create {}: {err}", dir.display()))?;
+    }
+    let json = serde_json::to_vec_pretty(store).map_err(|err| err.to_string())?;
+    fs::write(&path, json).map_err(|err| format!("Failed to write {}: {err}", path.display()))
+}
+
+pub fn import_gpl(path: &Path) -> Result<Palette, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    let fallback = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Imported".to_string());
+    parse_gpl(&text, &fallback)
+}
+
+pub fn export_gpl(palette: &Palette, path: &Path) -> Result<(), String> {
+    fs::write(path, to_gpl(palette))
+        .map_err(|err| format!("Failed to write {}: {err}", path.display()))
+}
+
+// GIMP palettes are a "GIMP Palette" header, optional Name/Columns lines and comments,
+// then one "R G B name" entry per line with 0-255 channels.
+pub fn parse_gpl(text: &str, fallback_name: &str) -> Result<Palette, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("Not a GIMP palette.".to_string());
+    }
+    let mut name = fallback_name.to_string();
+    let mut swatches = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Name:") {
+            name = value.trim().to_string();
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let mut channel = || -> Result<f32, String> {
+            fields
+                .next()
+                .and_then(|field| field.parse::<u8>().ok())
+                .map(|value| value as f32 / 255.0)
+                .ok_or_else(|| format!("Invalid palette entry on line {}.", number + 2))
+        };
+        let (red, green, blue) = (channel()?, channel()?, channel()?);
+        swatches.push(Swatch {
+            color: gdk::RGBA::new(red, green, blue, 1.0),
+            name: fields.collect::<Vec<_>>().join(" "),
+        });
+    }
+    if swatches.is_empty() {
+        return Err("Palette has no colors.".to_string());
+    }
+    Ok(Palette { name, swatches })
+}
+
+pub fn to_gpl(palette: &Palette) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut text = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", palette.name);
+    for swatch in &palette.swatches {
+        let color = &swatch.color;
+        text.push_str(&format!(
+            "{:3} {:3} {:3}\t{}\n",
+            channel(color.red()),
+            channel(color.green()),
+            channel(color.blue()),
+            swatch.name
+        ));
+    }
+    text
+}
+
+mod rgba_list {
+    use gtk::gdk;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colors: &[gdk::RGBA], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(colors.iter().map(|color| color.to_str().to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<gdk::RGBA>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| gdk::RGBA::parse(value.as_str()).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(swatch: &Swatch) -> [u8; 3] {
+        let channel = |value: f32| (value * 255.0).round() as u8;
+        let color = &swatch.color;
+        [channel(color.red()), channel(color.green()), channel(color.blue())]
+    }
+
+    fn swatch(red: u8, green: u8, blue: u8, name: &str) -> Swatch {
+        let channel = |value: u8| value as f32 / 255.0;
+        Swatch {
+            color: gdk::RGBA::new(channel(red), channel(green), channel(blue), 1.0),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_gpl() {
+        let palette = Palette {
+            name: "Brand colors".to_string(),
+            swatches: vec![
+                swatch(0, 0, 0, "Black"),
+                swatch(255, 128, 7, "Warm orange"),
+                swatch(18, 52, 86, ""),
+            ],
+        };
+        let parsed = parse_gpl(&to_gpl(&palette), "Fallback").unwrap();
+        assert_eq!(parsed.name, "Brand colors");
+        let swatches: Vec<_> = parsed.swatches.iter().map(|s| (bytes(s), s.name.as_str())).collect();
+        assert_eq!(
+            swatches,
+            [([0, 0, 0], "Black"), ([255, 128, 7], "Warm orange"), ([18, 52, 86], "")]
+        );
+    }
+
+    #[test]
+    fn requires_the_header() {
+        assert_eq!(
+            parse_gpl("255 0 0 Red\n", "Fallback").unwrap_err(),
+            "Not a GIMP palette."
+        );
+        assert_eq!(parse_gpl("", "Fallback").unwrap_err(), "Not a GIMP palette.");
+    }
+
+    #[test]
+    fn reports_short_and_invalid_rows_by_line() {
+        let short = "GIMP Palette\nName: Test\n255 0 0 Red\n12 34\n";
+        assert_eq!(
+            parse_gpl(short, "Fallback").unwrap_err(),
+            "Invalid palette entry on line 4."
+        );
+        let out_of_range = "GIMP Palette\n256 0 0 Too red\n";
+        assert_eq!(
+            parse_gpl(out_of_range, "Fallback").unwrap_err(),
+            "Invalid palette entry on line 2."
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let text = "GIMP Palette\r\n# exported by hand\r\n\r\n  #indented comment\r\n1 2 3\tFirst\r\n";
+        let palette = parse_gpl(text, "Fallback").unwrap();
+        assert_eq!(palette.name, "Fallback");
+        assert_eq!(palette.swatches.len(), 1);
+        assert_eq!(bytes(&palette.swatches[0]), [1, 2, 3]);
+        assert_eq!(palette.swatches[0].name, "First");
+
+        let empty = "GIMP Palette\nName: Empty\n# nothing here\n";
+        assert_eq!(parse_gpl(empty, "Fallback").unwrap_err(), "Palette has no colors.");
+    }
+
+    #[test]
+    fn reads_name_and_columns_headers() {
+        let text = "GIMP Palette\nName:   Solarized  \nColumns: 4\n#\n  0  43  54 base03\n";
+        let palette = parse_gpl(text, "Fallback").unwrap();
+        assert_eq!(palette.name, "Solarized");
+        assert_eq!(palette.swatches.len(), 1);
+        assert_eq!(bytes(&palette.swatches[0]), [0, 43, 54]);
+        assert_eq!(palette.swatches[0].name, "base03");
+    }
+}
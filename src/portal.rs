@@ -0,0 +1,163 @@
+use ashpd::desktop::screenshot::Screenshot;
+use ashpd::desktop::{Color, ResponseError};
+
+// Both calls go through the Screenshot portal on the session bus, so they can be pointed at a
+// mock implementation by running with a private DBUS_SESSION_BUS_ADDRESS.
+
+pub async fn capture(interactive: bool) -> Result<String, String> {
+    Screenshot::request()
+        .interactive(interactive)
+        .modal(true)
+        .send()
+        .await
+        .and_then(|request| request.response())
+        .map(|response| response.uri().to_string())
+        .map_err(describe)
+}
+
+pub async fn pick_color() -> Result<Color, String> {
+    Color::pick()
+        .send()
+        .await
+        .and_then(|request| request.response())
+        .map_err(describe)
+}
+
+// Dismissing the portal dialog isn't a failure, so it gets a plain message of its own.
+fn describe(err: ashpd::Error) -> String {
+    match err {
+        ashpd::Error::Response(ResponseError::Cancelled) => "Cancelled.".to_string(),
+        err => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use gtk::gio;
+    use gtk::glib;
+    use gtk::prelude::*;
+
+    use super::*;
+
+    const SCREENSHOT_XML: &str = r#"<node>
+  <interface name="org.freedesktop.portal.Screenshot">
+    <method name="PickColor">
+      <arg type="s" name="parent_window" direction="in"/>
+      <arg type="a{sv}" name="options" direction="in"/>
+      <arg type="o" name="handle" direction="out"/>
+    </method>
+    <property name="version" type="u" access="read"/>
+  </interface>
+</node>"#;
+
+    #[derive(Clone, Copy)]
+    enum Reply {
+        Color(f64, f64, f64),
+        Cancel,
+        Fail,
+    }
+
+    // Answers PickColor the way xdg-desktop-portal does: return the request handle, then send
+    // Response on it.
+    fn serve(connection: &gio::DBusConnection, reply: Rc<Cell<Reply>>) -> gio::RegistrationId {
+        let node = gio::DBusNodeInfo::for_xml(SCREENSHOT_XML).unwrap();
+        let interface = node
+            .lookup_interface("org.freedesktop.portal.Screenshot")
+            .unwrap();
+        connection
+            .register_object("/org/freedesktop/portal/desktop", &interface)
+            .method_call(move |connection, sender, _, _, _, parameters, invocation| {
+                let sender = sender.unwrap_or_default();
+                let options = glib::VariantDict::new(Some(&parameters.child_value(1)));
+                let token = options.lookup::<String>("handle_token").unwrap().unwrap();
+                let handle = format!(
+                    "/org/freedesktop/portal/desktop/request/{}/{token}",
+                    sender.trim_start_matches(':').replace('.', "_")
+                );
+                let results = glib::VariantDict::new(None);
+                let code = match reply.get() {
+                    Reply::Color(red, green, blue) => {
+                        results.insert_value("color", &(red, green, blue).to_variant());
+                        0u32
+                    }
+                    Reply::Cancel => 1,
+                    Reply::Fail => {
+                        invocation.return_dbus_error(
+                            "org.freedesktop.portal.Error.Failed",
+                            "No screen to pick from",
+                        );
+                        return;
+                    }
+                };
+                let path = glib::variant::ObjectPath::try_from(handle.clone()).unwrap();
+                invocation.return_value(Some(&(path,).to_variant()));
+                let response = glib::Variant::tuple_from_iter([code.to_variant(), results.end()]);
+                connection
+                    .emit_signal(
+                        Some(sender),
+                        &handle,
+                        "org.freedesktop.portal.Request",
+                        "Response",
+                        Some(&response),
+                    )
+                    .unwrap();
+            })
+            .property(|_, _, _, _, _| 2u32.to_variant())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn pick_color_goes_through_the_portal() {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let bus = gio::TestDBus::new(gio::TestDBusFlags::NONE);
+                bus.up();
+                let address = bus.bus_address().unwrap();
+                let connection = gio::DBusConnection::for_address_sync(
+                    &address,
+                    gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                        | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                    None,
+                    None::<&gio::Cancellable>,
+                )
+                .unwrap();
+                let reply = Rc::new(Cell::new(Reply::Cancel));
+                let registration = serve(&connection, reply.clone());
+                connection
+                    .call_sync(
+                        Some("org.freedesktop.DBus"),
+                        "/org/freedesktop/DBus",
+                        "org.freedesktop.DBus",
+                        "RequestName",
+                        Some(&("org.freedesktop.portal.Desktop", 0u32).to_variant()),
+                        None,
+                        gio::DBusCallFlags::NONE,
+                        -1,
+                        None::<&gio::Cancellable>,
+                    )
+                    .unwrap();
+
+                // zbus needs a tokio runtime, while the mock is served from this thread's context.
+                let runtime = tokio::runtime::Runtime::new().unwrap();
+                let pick = |next| {
+                    reply.set(next);
+                    context.block_on(runtime.spawn(pick_color())).unwrap()
+                };
+
+                let color = pick(Reply::Color(0.25, 0.5, 1.0)).unwrap();
+                assert_eq!((color.red(), color.green(), color.blue()), (0.25, 0.5, 1.0));
+                assert_eq!(pick(Reply::Cancel).unwrap_err(), "Cancelled.");
+                let err = pick(Reply::Fail).unwrap_err();
+                assert!(err.contains("No screen to pick from"), "{err}");
+
+                connection.unregister_object(registration).unwrap();
+                bus.down();
+            })
+            .unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use gtk::gdk;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::editor::Tool;
+use crate::export::{ImageFormat, SaveOptions};
+
+const PREFERENCES_FILE: &str = "settings.toml";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub tool: Tool,
+    #[serde(with = "crate::editor::rgba")]
+    pub color: gdk::RGBA,
+    pub stroke_width: f64,
+    pub text_size: f64,
+    pub capture_delay: f64,
+    pub interactive: bool,
+    pub secure_export: bool,
+    pub export_format: ImageFormat,
+    pub export_quality: u32,
+    pub png_compression: u32,
+    #[serde(with = "crate::editor::rgba")]
+    pub flatten_color: gdk::RGBA,
+    pub fit_to_window: bool,
+    pub window_width: i32,
+    pub window_height: i32,
+    pub maximized: bool,
+    // Keyed by action name, see shortcuts::GROUPS. Kept last since TOML tables follow plain keys.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub shortcuts: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            tool: Tool::Pen,
+            color: gdk::RGBA::new(1.0, 0.30, 0.30, 1.0),
+            stroke_width: 4.0,
+            text_size: 22.0,
+            capture_delay: 0.0,
+            interactive: true,
+            secure_export: true,
+            export_format: ImageFormat::Png,
+            export_quality: SaveOptions::default().quality,
+            png_compression: SaveOptions::default().png_compression,
+            flatten_color: SaveOptions::default().background,
+            fit_to_window: true,
+            window_width: 1400,
+            window_height: 900,
+            maximized: true,
+            shortcuts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Preferences {
+    pub fn save_options(&self) -> SaveOptions {
+        SaveOptions {
+            quality: self.export_quality,
+            png_compression: self.png_compression,
+            background: self.flatten_color,
+        }
+    }
+}
+
+pub fn path() -> PathBuf {
+    config::dir().join(PREFERENCES_FILE)
+}
+
+pub fn load() -> Result<Preferences, String> {
+    Ok(config::load_toml(&path())?.unwrap_or_default())
+}
+
+pub fn save(preferences: &Preferences) -> Result<(), String> {
+    let dir = config::dir();
+    fs::create_dir_all(&dir).map_err(|err| format!("Failed to create {}: {err}", dir.display()))?;
+    let path = dir.join(PREFERENCES_FILE);
+    let text = toml::to_string_pretty(preferences).map_err(|err| err.to_string())?;
+    fs::write(&path, text).map_err(|err| format!("Failed to write {}: {err}", path.display()))
+}
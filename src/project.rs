@@ -0,0 +1,245 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use gdk_pixbuf::Pixbuf;
+use gtk::gdk;
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+
+use crate::editor::{Annotation, EditorState, Rect, TextFont, Tool};
+use crate::redact;
+
+pub const PROJECT_EXTENSION: &str = "greatshot";
+
+const PROJECT_FORMAT: &str = "greatshot-project";
+// Bump whenever annotations or project fields change in a way older builds can't read.
+// Version 2 added fill, highlighter, fonts, callouts, steps, redaction modes and regions,
+// magnifiers and spotlights.
+const PROJECT_VERSION: u32 = 2;
+
+// Read before the full file so a newer project is reported as such rather than failing on
+// an annotation type this build doesn't know.
+#[derive(Deserialize)]
+struct ProjectHeader {
+    format: String,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    format: String,
+    version: u32,
+    background: String,
+    annotations: Vec<Annotation>,
+    tool: Tool,
+    #[serde(with = "crate::editor::rgba")]
+    color: gdk::RGBA,
+    stroke_width: f64,
+    text_size: f64,
+    #[serde(default)]
+    text_font: TextFont,
+    crop_rect: Option<Rect>,
+}
+
+pub struct Project {
+    pub background: Pixbuf,
+    pub annotations: Vec<Annotation>,
+    pub tool: Tool,
+    pub color: gdk::RGBA,
+    pub stroke_width: f64,
+    pub text_size: f64,
+    pub text_font: TextFont,
+    pub crop_rect: Option<Rect>,
+}
+
+impl Project {
+    pub fn apply(self, state: &mut EditorState) {
+        state.set_background(self.background);
+        state.annotations = self.annotations;
+        state.tool = self.tool;
+        state.color = self.color;
+        state.stroke_width = self.stroke_width;
+        state.text_size = self.text_size;
+        state.text_font = self.text_font;
+        state.crop_rect = self.crop_rect;
+    }
+}
+
+pub fn save_project(state: &EditorState, path: &Path) -> Result<(), String> {
+    if state.background.is_none() {
+        return Err("Nothing to save yet.".to_string());
+    }
+    // With secure export on, blurs are burned into the stored background so the
+    // original pixels never reach disk; otherwise they stay editable.
+    let background = if state.secure_export {
+        redact::secure_background(state)?
+    } else {
+        state.background.clone().unwrap()
+    };
+    let png = background
+        .save_to_bufferv("png", &[])
+        .map_err(|err| format!("Failed to encode background: {err}"))?;
+    let file = ProjectFile {
+        format: PROJECT_FORMAT.to_string(),
+        version: PROJECT_VERSION,
+        background: glib::base64_encode(&png).to_string(),
+        annotations: state.annotations.clone(),
+        tool: state.tool,
+        color: state.color,
+        stroke_width: state.stroke_width,
+        text_size: state.text_size,
+        text_font: state.text_font.clone(),
+        crop_rect: state.crop_rect,
+    };
+    let json = serde_json::to_vec_pretty(&file).map_err(|err| err.to_string())?;
+    fs::write(path, json).map_err(|err| err.to_string())
+}
+
+pub fn load_project(path: &Path) -> Result<Project, String> {
+    let data = fs::read(path).map_err(|err| err.to_string())?;
+    let header: ProjectHeader =
+        serde_json::from_slice(&data).map_err(|err| format!("Invalid project file: {err}"))?;
+    if header.format != PROJECT_FORMAT {
+        return Err("Not a GreatShot project.".to_string());
+    }
+    if header.version > PROJECT_VERSION {
+        return Err(format!(
+            "Project version {} is newer than supported version {PROJECT_VERSION}.",
+            header.version
+        ));
+    }
+    let file: ProjectFile =
+        serde_json::from_slice(&data).map_err(|err| format!("Invalid project file: {err}"))?;
+    let png = glib::base64_decode(&file.background);
+    let background = Pixbuf::from_read(Cursor::new(png))
+        .map_err(|err| format!("Failed to decode background: {err}"))?;
+    Ok(Project {
+        background,
+        annotations: file.annotations,
+        tool: file.tool,
+        color: file.color,
+        stroke_width: file.stroke_width,
+        text_size: file.text_size,
+        text_font: file.text_font,
+        crop_rect: file.crop_rect,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use gdk_pixbuf::Colorspace;
+
+    use super::*;
+    use crate::editor::{BlurMode, BlurRegion, Point};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "greatshot-{name}-{}.{PROJECT_EXTENSION}",
+            std::process::id()
+        ))
+    }
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn rect(x1: f64, y1: f64, x2: f64, y2: f64) -> Rect {
+        Rect { x1, y1, x2, y2 }
+    }
+
+    fn pixel(pixbuf: &Pixbuf, x: usize, y: usize) -> [u8; 4] {
+        let bytes = pixbuf.read_pixel_bytes();
+        let offset = y * pixbuf.rowstride() as usize + x * pixbuf.n_channels() as usize;
+        [bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]
+    }
+
+    #[test]
+    fn round_trips_annotations_and_background() {
+        let background = Pixbuf::new(Colorspace::Rgb, true, 8, 64, 64).unwrap();
+        background.fill(0x336699ff);
+        let patch = Pixbuf::new(Colorspace::Rgb, true, 8, 16, 16).unwrap();
+        patch.fill(0xffcc00ff);
+        patch.copy_area(0, 0, 16, 16, &background, 0, 0);
+        let mut state = EditorState::new();
+        state.set_background(background.clone());
+        state.annotations = vec![
+            Annotation::Text {
+                pos: point(5.0, 30.0),
+                text: "Hello\nworld".to_string(),
+                color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+                size: 14.0,
+                font: TextFont::default(),
+            },
+            Annotation::Blur {
+                rect: rect(30.0, 30.0, 60.0, 60.0),
+                mode: BlurMode::Solid,
+                strength: 16,
+                region: Some(BlurRegion::Polygon {
+                    points: vec![point(30.0, 30.0), point(60.0, 30.0), point(45.0, 60.0)],
+                }),
+                include_below: true,
+            },
+        ];
+        state.tool = Tool::Blur;
+        state.stroke_width = 7.0;
+        state.crop_rect = Some(rect(1.0, 1.0, 50.0, 50.0));
+        state.secure_export = true;
+
+        let path = temp_path("round-trip");
+        save_project(&state, &path).unwrap();
+        let project = load_project(&path);
+        let _ = fs::remove_file(&path);
+        let project = project.unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&project.annotations).unwrap(),
+            serde_json::to_value(&state.annotations).unwrap()
+        );
+        assert_eq!(project.tool, Tool::Blur);
+        assert_eq!(project.stroke_width, 7.0);
+        assert!(project.crop_rect.is_some());
+        // Secure export burns the solid blur in as black; the rest stays as it was.
+        assert_eq!(pixel(&project.background, 45, 40), [0, 0, 0, 255]);
+        assert_eq!(pixel(&project.background, 5, 5), [0xff, 0xcc, 0x00, 0xff]);
+        assert_eq!(pixel(&project.background, 60, 10), [0x33, 0x66, 0x99, 0xff]);
+
+        state.secure_export = false;
+        save_project(&state, &path).unwrap();
+        let project = load_project(&path);
+        let _ = fs::remove_file(&path);
+        let project = project.unwrap();
+        assert_eq!((project.background.width(), project.background.height()), (64, 64));
+        for y in 0..64 {
+            for x in 0..64 {
+                assert_eq!(pixel(&project.background, x, y), pixel(&background, x, y), "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn reports_newer_versions_before_parsing_annotations() {
+        let path = temp_path("newer");
+        let json = serde_json::json!({
+            "format": PROJECT_FORMAT,
+            "version": PROJECT_VERSION + 1,
+            "annotations": [{ "type": "hologram" }],
+        });
+        fs::write(&path, json.to_string()).unwrap();
+        let result = load_project(&path);
+        let _ = fs::remove_file(&path);
+        let err = result.err().unwrap();
+        assert!(err.contains("newer than supported"), "{err}");
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let path = temp_path("other");
+        fs::write(&path, r#"{ "format": "something-else", "version": 1 }"#).unwrap();
+        let result = load_project(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(result.err().unwrap(), "Not a GreatShot project.");
+    }
+}
@@ -0,0 +1,658 @@
+use gdk_pixbuf::Pixbuf;
+use gtk::cairo;
+use gtk::glib;
+
+use crate::editor::{self, Annotation, BlurMode, BlurRegion, EditorState, Rect};
+
+pub const MIN_BLOCK_SIZE: i32 = 16;
+const NOISE: i32 = 24;
+
+#[derive(Clone, Copy)]
+struct Region {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Region {
+    fn overlaps(self, other: Region) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    fn pixels(self) -> impl Iterator<Item = (usize, usize)> {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+}
+
+// Pixels covered by a redaction within its bounding region; `None` covers all of them.
+struct Mask {
+    region: Region,
+    pixels: Option<Vec<bool>>,
+}
+
+impl Mask {
+    fn covers(&self, x: usize, y: usize) -> bool {
+        match &self.pixels {
+            Some(pixels) => pixels[(y - self.region.y) * self.region.width + x - self.region.x],
+            None => true,
+        }
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.region.pixels().filter(|&(x, y)| self.covers(x, y))
+    }
+}
+
+struct Blur<'a> {
+    index: usize,
+    rect: Rect,
+    shape: Option<&'a BlurRegion>,
+    mask: Mask,
+    block: usize,
+    solid: bool,
+    include_below: bool,
+}
+
+// Alpha coverage of whatever `paint` draws, one byte per pixel of `region`.
+fn rasterize(region: Region, paint: impl FnOnce(&cairo::Context)) -> Result<Vec<u8>, String> {
+    let mut surface =
+        cairo::ImageSurface::create(cairo::Format::A8, region.width as i32, region.height as i32)
+            .map_err(|err| format!("Failed to rasterize redaction: {err}"))?;
+    {
+        let ctx = cairo::Context::new(&surface)
+            .map_err(|err| format!("Failed to rasterize redaction: {err}"))?;
+        ctx.translate(-(region.x as f64), -(region.y as f64));
+        ctx.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+        paint(&ctx);
+    }
+    surface.flush();
+    let stride = surface.stride() as usize;
+    let data = surface
+        .data()
+        .map_err(|err| format!("Failed to rasterize redaction: {err}"))?;
+    let mut alpha = Vec::with_capacity(region.width * region.height);
+    for y in 0..region.height {
+        alpha.extend_from_slice(&data[y * stride..y * stride + region.width]);
+    }
+    Ok(alpha)
+}
+
+// Any partially covered pixel counts, so antialiased edges are destroyed too.
+fn shape_mask(region: Region, rect: Rect, shape: &BlurRegion) -> Result<Vec<bool>, String> {
+    let alpha = rasterize(region, |ctx| {
+        editor::paint_redaction_shape(ctx, rect, Some(shape))
+    })?;
+    Ok(alpha.into_iter().map(|value| value > 0).collect())
+}
+
+fn blurs<'a>(state: &'a EditorState, image: &Image) -> Result<Vec<Blur<'a>>, String> {
+    let mut blurs = Vec::new();
+    for (index, annotation) in state.annotations.iter().enumerate() {
+        let Annotation::Blur {
+            rect,
+            mode,
+            strength,
+            region: shape,
+            include_below,
+        } = annotation
+        else {
+            continue;
+        };
+        let Some(region) = image.region(*rect) else {
+            continue;
+        };
+        let pixels = match shape {
+            Some(shape) => Some(shape_mask(region, *rect, shape)?),
+            None => None,
+        };
+        let solid = *mode == BlurMode::Solid;
+        let block = if solid {
+            region.width.max(region.height)
+        } else {
+            (*strength).max(MIN_BLOCK_SIZE) as usize
+        };
+        blurs.push(Blur {
+            index,
+            rect: *rect,
+            shape: shape.as_ref(),
+            mask: Mask { region, pixels },
+            block,
+            solid,
+            include_below: *include_below,
+        });
+    }
+    Ok(blurs)
+}
+
+// Where each magnifier samples from, grown by a pixel for the edges it pads out.
+fn magnifiers(state: &EditorState, image: &Image) -> Vec<(usize, Region)> {
+    let mut magnifiers = Vec::new();
+    for (index, annotation) in state.annotations.iter().enumerate() {
+        let Annotation::Magnify { source, .. } = annotation else {
+            continue;
+        };
+        let (x, y, w, h) = source.normalized();
+        let grown = Rect {
+            x1: x - 1.0,
+            y1: y - 1.0,
+            x2: x + w + 1.0,
+            y2: y + h + 1.0,
+        };
+        if let Some(region) = image.region(grown) {
+            magnifiers.push((index, region));
+        }
+    }
+    magnifiers
+}
+
+fn bake(blur: &Blur, base: &mut Image, scene: &mut Image) {
+    if blur.include_below {
+        scene.destroy(&blur.mask, blur.block, blur.solid);
+    } else {
+        // Other blurs only sample the screenshot, but still cover what's drawn beneath them.
+        base.destroy(&blur.mask, blur.block, blur.solid);
+        scene.copy_masked(base, &blur.mask);
+    }
+}
+
+fn copy_background(state: &EditorState) -> Result<Pixbuf, String> {
+    state
+        .background
+        .as_ref()
+        .ok_or_else(|| "Nothing to export yet.".to_string())?
+        .copy()
+        .ok_or_else(|| "Failed to copy background.".to_string())
+}
+
+// Bakes every redaction into a copy of the background and checks the result, so the
+// original pixels under a redaction never leave the editor.
+pub fn secure_background(state: &EditorState) -> Result<Pixbuf, String> {
+    let mut image = Image::from_pixbuf(&copy_background(state)?);
+    let blurs = blurs(state, &image)?;
+    let mut baked = Vec::with_capacity(blurs.len());
+    for blur in &blurs {
+        image.destroy(&blur.mask, blur.block, blur.solid);
+        baked.push(image.masked_pixels(&blur.mask));
+    }
+    for (blur, baked) in blurs.iter().zip(&baked) {
+        let coverage = image.coverage(&blurs, blur.index, |_| {})?;
+        image.verify(blur, baked, &coverage)?;
+    }
+    Ok(image.pixbuf())
+}
+
+// Bakes each redaction at its place in the stack: everything beneath a blur is flattened
+// first and the destroyed pixels replace it, so annotations a blur hides stay hidden and
+// ones drawn over it stay visible. A magnifier beneath a blur would enlarge what the blur
+// hides, so every later blur its source overlaps is baked before the magnifier is drawn.
+// The rendered result is then checked pixel by pixel.
+pub fn secure_render(state: &EditorState) -> Result<Pixbuf, String> {
+    let background = copy_background(state)?;
+    let mut base = Image::from_pixbuf(&background);
+    let mut scene = Image::from_pixbuf(&background);
+    let blurs = blurs(state, &base)?;
+    let magnifiers = magnifiers(state, &base);
+    let mut baked = Vec::with_capacity(blurs.len());
+    let mut flattened = 0;
+    for blur in &blurs {
+        let pending = flattened..blur.index;
+        for &(index, source) in magnifiers
+            .iter()
+            .filter(|(index, _)| pending.contains(index))
+        {
+            let mut exposed = blurs
+                .iter()
+                .filter(|later| later.index > index && later.mask.region.overlaps(source))
+                .peekable();
+            if exposed.peek().is_none() {
+                continue;
+            }
+            if index > flattened {
+                scene = scene.flatten(state, flattened..index)?;
+                flattened = index;
+            }
+            for later in exposed {
+                bake(later, &mut base, &mut scene);
+            }
+        }
+        if blur.index > flattened {
+            scene = scene.flatten(state, flattened..blur.index)?;
+            flattened = blur.index;
+        }
+        bake(blur, &mut base, &mut scene);
+        baked.push(scene.masked_pixels(&blur.mask));
+    }
+    let output = scene.flatten(state, flattened..state.annotations.len())?;
+    for (blur, baked) in blurs.iter().zip(&baked) {
+        let above = blur.index + 1..state.annotations.len();
+        let coverage = output.coverage(&blurs, blur.index, |ctx| {
+            editor::paint_coverage(state, ctx, above)
+        })?;
+        output.verify(blur, baked, &coverage)?;
+    }
+    Ok(output.pixbuf())
+}
+
+struct Image {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    channels: usize,
+    rowstride: usize,
+    has_alpha: bool,
+}
+
+impl Image {
+    fn from_pixbuf(pixbuf: &Pixbuf) -> Self {
+        Self {
+            data: pixbuf.read_pixel_bytes().to_vec(),
+            width: pixbuf.width() as usize,
+            height: pixbuf.height() as usize,
+            channels: pixbuf.n_channels() as usize,
+            rowstride: pixbuf.rowstride() as usize,
+            has_alpha: pixbuf.has_alpha(),
+        }
+    }
+
+    fn pixbuf(&self) -> Pixbuf {
+        Pixbuf::from_bytes(
+            &glib::Bytes::from(&self.data),
+            gdk_pixbuf::Colorspace::Rgb,
+            self.has_alpha,
+            8,
+            self.width as i32,
+            self.height as i32,
+            self.rowstride as i32,
+        )
+    }
+
+    // Whole pixels touched by the rectangle, so partly covered edges are included.
+    fn region(&self, rect: Rect) -> Option<Region> {
+        let (x, y, w, h) = rect.normalized();
+        let x1 = x.floor().clamp(0.0, self.width as f64) as usize;
+        let y1 = y.floor().clamp(0.0, self.height as f64) as usize;
+        let x2 = (x + w).ceil().clamp(0.0, self.width as f64) as usize;
+        let y2 = (y + h).ceil().clamp(0.0, self.height as f64) as usize;
+        if x2 <= x1 || y2 <= y1 {
+            return None;
+        }
+        Some(Region {
+            x: x1,
+            y: y1,
+            width: x2 - x1,
+            height: y2 - y1,
+        })
+    }
+
+    fn flatten(
+        &self,
+        state: &EditorState,
+        layers: std::ops::Range<usize>,
+    ) -> Result<Image, String> {
+        editor::render_layers(state, &self.pixbuf(), true, layers)
+            .map(|pixbuf| Image::from_pixbuf(&pixbuf))
+            .ok_or_else(|| "Failed to render image.".to_string())
+    }
+
+    // Alpha of everything drawn over the blur at `index`, where the output may differ from
+    // what it baked: later blurs plus whatever `paint` draws.
+    fn coverage(
+        &self,
+        blurs: &[Blur],
+        index: usize,
+        paint: impl FnOnce(&cairo::Context),
+    ) -> Result<Vec<u8>, String> {
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+        rasterize(region, |ctx| {
+            paint(ctx);
+            ctx.set_source_rgba(0.0, 0.0, 0.0, 1.0);
+            for blur in blurs.iter().filter(|blur| blur.index > index) {
+                editor::paint_redaction_shape(ctx, blur.rect, blur.shape);
+            }
+        })
+    }
+
+    fn cells(region: Region, block: usize) -> impl Iterator<Item = Region> {
+        let block = block.max(1);
+        (region.y..region.y + region.height)
+            .step_by(block)
+            .flat_map(move |y| {
+                (region.x..region.x + region.width)
+                    .step_by(block)
+                    .map(move |x| Region {
+                        x,
+                        y,
+                        width: block.min(region.x + region.width - x),
+                        height: block.min(region.y + region.height - y),
+                    })
+            })
+    }
+
+    fn offset(&self, x: usize, y: usize) -> usize {
+        y * self.rowstride + x * self.channels
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let offset = self.offset(x, y);
+        let mut value = [0, 0, 0, 255];
+        value[..self.channels].copy_from_slice(&self.data[offset..offset + self.channels]);
+        value
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, value: [u8; 4]) {
+        let offset = self.offset(x, y);
+        self.data[offset..offset + self.channels].copy_from_slice(&value[..self.channels]);
+    }
+
+    fn masked_pixels(&self, mask: &Mask) -> Vec<[u8; 4]> {
+        mask.pixels().map(|(x, y)| self.pixel(x, y)).collect()
+    }
+
+    fn copy_masked(&mut self, source: &Image, mask: &Mask) {
+        for (x, y) in mask.pixels() {
+            self.set_pixel(x, y, source.pixel(x, y));
+        }
+    }
+
+    fn destroy(&mut self, mask: &Mask, block: usize, solid: bool) {
+        for cell in Self::cells(mask.region, block) {
+            let value = if solid {
+                [0, 0, 0, 255]
+            } else {
+                match self.noisy_average(mask, cell) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            };
+            for (x, y) in cell.pixels() {
+                if mask.covers(x, y) {
+                    self.set_pixel(x, y, value);
+                }
+            }
+        }
+    }
+
+    fn noisy_average(&self, mask: &Mask, cell: Region) -> Option<[u8; 4]> {
+        let mut sums = [0u64; 4];
+        let mut count = 0u64;
+        for (x, y) in cell.pixels() {
+            if !mask.covers(x, y) {
+                continue;
+            }
+            count += 1;
+            let offset = self.offset(x, y);
+            for (sum, value) in sums
+                .iter_mut()
+                .zip(&self.data[offset..offset + self.channels])
+            {
+                *sum += *value as u64;
+            }
+        }
+        if count == 0 {
+            return None;
+        }
+        let mut value = [0, 0, 0, 255];
+        for (channel, sum) in sums.iter().enumerate().take(self.channels) {
+            // Noise stops the block averages from being matched against candidate text.
+            let noise = if channel < 3 {
+                glib::random_int_range(-NOISE, NOISE + 1)
+            } else {
+                0
+            };
+            value[channel] = ((sum / count) as i32 + noise).clamp(0, 255) as u8;
+        }
+        Some(value)
+    }
+
+    // Every cell of the blur must have baked to a single value, and every pixel nothing else
+    // was drawn over must still hold it.
+    fn verify(&self, blur: &Blur, baked: &[[u8; 4]], coverage: &[u8]) -> Result<(), String> {
+        let failed = |x: usize, y: usize| Err(format!("Could not verify redaction at {x}, {y}."));
+        let region = blur.mask.region;
+        let columns = region.width.div_ceil(blur.block);
+        let mut cells = vec![None; columns * region.height.div_ceil(blur.block)];
+        for ((x, y), expected) in blur.mask.pixels().zip(baked) {
+            let cell = (y - region.y) / blur.block * columns + (x - region.x) / blur.block;
+            if *cells[cell].get_or_insert(*expected) != *expected {
+                return failed(x, y);
+            }
+            if coverage[y * self.width + x] == 0 && !same_pixel(*expected, self.pixel(x, y)) {
+                return failed(x, y);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Cairo stores premultiplied colors, so translucent pixels can come back slightly off.
+fn same_pixel(expected: [u8; 4], actual: [u8; 4]) -> bool {
+    let alpha = expected[3] as i32;
+    if alpha != actual[3] as i32 {
+        return false;
+    }
+    if alpha == 0 {
+        return true;
+    }
+    let tolerance = if alpha == 255 { 0 } else { 255 / alpha + 1 };
+    expected[..3]
+        .iter()
+        .zip(&actual[..3])
+        .all(|(a, b)| (*a as i32 - *b as i32).abs() <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use gdk_pixbuf::Colorspace;
+    use gtk::gdk;
+
+    use super::*;
+    use crate::editor::Point;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    // Alternating black and white pixels average to mid grey, which no original pixel matches.
+    fn checkerboard(width: i32, height: i32) -> Pixbuf {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, width, height).unwrap();
+        for y in 0..height as u32 {
+            for x in 0..width as u32 {
+                let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+                pixbuf.put_pixel(x, y, value, value, value, 255);
+            }
+        }
+        pixbuf
+    }
+
+    fn grey(width: i32, height: i32) -> Pixbuf {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, width, height).unwrap();
+        pixbuf.fill(0x808080ff);
+        pixbuf
+    }
+
+    fn rect(x1: f64, y1: f64, x2: f64, y2: f64) -> Rect {
+        Rect { x1, y1, x2, y2 }
+    }
+
+    fn blur(rect: Rect, mode: BlurMode, include_below: bool) -> Annotation {
+        Annotation::Blur {
+            rect,
+            mode,
+            strength: 4,
+            region: None,
+            include_below,
+        }
+    }
+
+    fn red_box(rect: Rect) -> Annotation {
+        Annotation::Rect {
+            rect,
+            color: gdk::RGBA::new(1.0, 0.0, 0.0, 1.0),
+            width: 2.0,
+            fill: Some(gdk::RGBA::new(1.0, 0.0, 0.0, 1.0)),
+        }
+    }
+
+    fn state(background: Pixbuf, annotations: Vec<Annotation>) -> EditorState {
+        let mut state = EditorState::new();
+        state.background = Some(background);
+        state.annotations = annotations;
+        state
+    }
+
+    #[test]
+    fn no_original_pixel_survives_under_the_mask() {
+        let original = checkerboard(100, 80);
+        for mode in [BlurMode::Pixelate, BlurMode::Gaussian] {
+            let blur = blur(rect(10.5, 10.5, 50.2, 40.7), mode, false);
+            let state = state(original.copy().unwrap(), vec![blur]);
+            let baked = Image::from_pixbuf(&secure_background(&state).unwrap());
+            let source = Image::from_pixbuf(&original);
+            for y in 0..80 {
+                for x in 0..100 {
+                    let inside = (10..51).contains(&x) && (10..41).contains(&y);
+                    let survived = baked.pixel(x, y) == source.pixel(x, y);
+                    assert_eq!(survived, !inside, "{mode:?} at {x}, {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_blur_is_baked_into_the_project_background() {
+        let original = checkerboard(100, 80);
+        let blurs = vec![
+            blur(rect(0.0, 0.0, 30.0, 30.0), BlurMode::Pixelate, false),
+            blur(rect(20.0, 20.0, 60.0, 60.0), BlurMode::Pixelate, true),
+        ];
+        let state = state(original.copy().unwrap(), blurs);
+        let baked = Image::from_pixbuf(&secure_background(&state).unwrap());
+        let source = Image::from_pixbuf(&original);
+        for y in 0..60 {
+            for x in 0..60 {
+                if x < 30 && y < 30 || x >= 20 && y >= 20 {
+                    assert_ne!(baked.pixel(x, y), source.pixel(x, y), "at {x}, {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blurs_hide_annotations_beneath_them_and_not_above() {
+        for include_below in [false, true] {
+            let pen = Annotation::Pen {
+                points: vec![Point { x: 0.0, y: 40.5 }, Point { x: 100.0, y: 40.5 }],
+                color: gdk::RGBA::new(0.0, 1.0, 0.0, 1.0),
+                width: 5.0,
+                highlighter: false,
+            };
+            let annotations = vec![
+                red_box(rect(20.0, 20.0, 60.0, 60.0)),
+                blur(
+                    rect(10.0, 10.0, 70.0, 70.0),
+                    BlurMode::Pixelate,
+                    include_below,
+                ),
+                pen,
+            ];
+            let state = state(grey(100, 100), annotations);
+            let output = Image::from_pixbuf(&secure_render(&state).unwrap());
+            // The box's edges run through these cells, but each must come out as one block.
+            for cell in Image::cells(
+                Region {
+                    x: 10,
+                    y: 10,
+                    width: 60,
+                    height: 60,
+                },
+                16,
+            ) {
+                if cell.y != 10 && cell.y != 58 {
+                    continue;
+                }
+                let first = output.pixel(cell.x, cell.y);
+                for (x, y) in cell.pixels() {
+                    assert_eq!(output.pixel(x, y), first, "at {x}, {y}");
+                    if !include_below {
+                        assert_ne!(output.pixel(x, y), RED, "at {x}, {y}");
+                    }
+                }
+            }
+            assert_eq!(output.pixel(50, 40), [0, 255, 0, 255]);
+            assert_eq!(output.pixel(90, 90), [128, 128, 128, 255]);
+        }
+    }
+
+    #[test]
+    fn solid_blur_covers_the_flattened_scene() {
+        let annotations = vec![
+            red_box(rect(20.0, 20.0, 60.0, 60.0)),
+            blur(rect(10.0, 10.0, 70.0, 70.0), BlurMode::Solid, false),
+            red_box(rect(80.0, 80.0, 95.0, 95.0)),
+        ];
+        let state = state(grey(100, 100), annotations);
+        let output = Image::from_pixbuf(&secure_render(&state).unwrap());
+        assert_eq!(output.pixel(40, 40), [0, 0, 0, 255]);
+        assert_eq!(output.pixel(88, 88), RED);
+    }
+
+    #[test]
+    fn magnifiers_beneath_a_blur_enlarge_the_redacted_pixels() {
+        for (mode, include_below) in [(BlurMode::Pixelate, false), (BlurMode::Solid, true)] {
+            let magnifier = Annotation::Magnify {
+                source: rect(20.0, 20.0, 40.0, 40.0),
+                rect: rect(60.0, 60.0, 95.0, 95.0),
+                color: gdk::RGBA::new(0.0, 0.0, 1.0, 1.0),
+                width: 2.0,
+                connectors: false,
+            };
+            let annotations = vec![
+                red_box(rect(20.0, 20.0, 40.0, 40.0)),
+                magnifier,
+                blur(rect(10.0, 10.0, 50.0, 50.0), mode, include_below),
+            ];
+            let state = state(grey(100, 100), annotations);
+            let output = Image::from_pixbuf(&secure_render(&state).unwrap());
+            for y in 62..93 {
+                for x in 62..93 {
+                    let pixel = output.pixel(x, y);
+                    assert_ne!(pixel, RED, "{mode:?} at {x}, {y}");
+                    if mode == BlurMode::Solid {
+                        assert_eq!(pixel, [0, 0, 0, 255], "at {x}, {y}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn verification_fails_when_a_baked_pixel_changes() {
+        let state = state(
+            checkerboard(100, 80),
+            vec![blur(
+                rect(10.0, 10.0, 50.0, 40.0),
+                BlurMode::Pixelate,
+                false,
+            )],
+        );
+        let mut image = Image::from_pixbuf(state.background.as_ref().unwrap());
+        let blurs = blurs(&state, &image).unwrap();
+        image.destroy(&blurs[0].mask, blurs[0].block, blurs[0].solid);
+        let baked = image.masked_pixels(&blurs[0].mask);
+        let mut coverage = vec![0; 100 * 80];
+        assert!(image.verify(&blurs[0], &baked, &coverage).is_ok());
+
+        image.set_pixel(30, 20, [0, 0, 0, 255]);
+        assert!(image.verify(&blurs[0], &baked, &coverage).is_err());
+        coverage[20 * 100 + 30] = 255;
+        assert!(image.verify(&blurs[0], &baked, &coverage).is_ok());
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gdk_pixbuf::Pixbuf;
+use serde::Deserialize;
+
+use crate::editor::{self, Annotation, EditorState, Rect};
+use crate::export::{self, ImageFormat, SaveOptions};
+
+const USAGE: &str = "Usage: greatshot render --input IMAGE [--annotations SPEC.json|SPEC.toml] [--secure] --output OUTPUT";
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    #[serde(default)]
+    crop: Option<Rect>,
+}
+
+struct RenderArgs {
+    input: PathBuf,
+    annotations: Option<PathBuf>,
+    output: PathBuf,
+    secure: bool,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let Some(args) = parse_args(args)? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+
+    let background = Pixbuf::from_file(&args.input)
+        .map_err(|err| format!("Failed to load {}: {err}", args.input.display()))?;
+    let spec = match args.annotations.as_deref() {
+        Some(path) => load_spec(path)?,
+        None => Spec::default(),
+    };
+    let pixbuf = render(background, spec, args.secure)?;
+    let (path, format) = export::resolve_path(args.output, ImageFormat::Png)?;
+    export::save(&pixbuf, &path, format, &SaveOptions::default())
+}
+
+fn render(background: Pixbuf, spec: Spec, secure: bool) -> Result<Pixbuf, String> {
+    let mut state = EditorState::new();
+    state.set_background(background);
+    state.secure_export = secure;
+    state.annotations = spec.annotations;
+    if let Some(rect) = spec.crop
+        && !editor::apply_crop(&mut state, rect)
+    {
+        return Err("Crop rectangle is empty.".to_string());
+    }
+    editor::export_pixbuf(&state)
+}
+
+fn parse_args(args: &[String]) -> Result<Option<RenderArgs>, String> {
+    let mut input = None;
+    let mut annotations = None;
+    let mut output = None;
+    let mut secure = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let slot = match arg.as_str() {
+            "-s" | "--secure" => {
+                secure = true;
+                continue;
+            }
+            "-i" | "--input" => &mut input,
+            "-a" | "--annotations" => &mut annotations,
+            "-o" | "--output" => &mut output,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("Unknown argument: {other}\n{USAGE}")),
+        };
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("Missing value for {arg}\n{USAGE}"))?;
+        *slot = Some(PathBuf::from(value));
+    }
+    Ok(Some(RenderArgs {
+        input: input.ok_or_else(|| format!("Missing --input\n{USAGE}"))?,
+        annotations,
+        output: output.ok_or_else(|| format!("Missing --output\n{USAGE}"))?,
+        secure,
+    }))
+}
+
+fn load_spec(path: &Path) -> Result<Spec, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    let toml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+    parse_spec(&text, toml)
+}
+
+fn parse_spec(text: &str, toml: bool) -> Result<Spec, String> {
+    let spec = if toml {
+        toml::from_str(text).map_err(|err| err.to_string())
+    } else {
+        serde_json::from_str(text).map_err(|err| err.to_string())
+    };
+    spec.map_err(|err| format!("Invalid annotation spec: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use gdk_pixbuf::Colorspace;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<RenderArgs>, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args)
+    }
+
+    fn pixel(pixbuf: &Pixbuf, x: usize, y: usize) -> [u8; 4] {
+        let bytes = pixbuf.read_pixel_bytes();
+        let offset = y * pixbuf.rowstride() as usize + x * pixbuf.n_channels() as usize;
+        let alpha = if pixbuf.has_alpha() {
+            bytes[offset + 3]
+        } else {
+            255
+        };
+        [bytes[offset], bytes[offset + 1], bytes[offset + 2], alpha]
+    }
+
+    fn background() -> Pixbuf {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, true, 8, 32, 32).unwrap();
+        pixbuf.fill(0xffffffff);
+        pixbuf
+    }
+
+    #[test]
+    fn parses_short_and_long_flags() {
+        let parsed = args(&[
+            "-i",
+            "in.png",
+            "--annotations",
+            "spec.toml",
+            "--secure",
+            "-o",
+            "out.png",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(parsed.input, PathBuf::from("in.png"));
+        assert_eq!(parsed.annotations, Some(PathBuf::from("spec.toml")));
+        assert_eq!(parsed.output, PathBuf::from("out.png"));
+        assert!(parsed.secure);
+
+        let parsed = args(&["--input", "in.png", "--output", "out.png"])
+            .unwrap()
+            .unwrap();
+        assert!(parsed.annotations.is_none());
+        assert!(!parsed.secure);
+        assert!(args(&["--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_flags() {
+        let err = args(&["--output", "out.png"]).err().unwrap();
+        assert!(err.starts_with("Missing --input"), "{err}");
+        let err = args(&["--input", "in.png"]).err().unwrap();
+        assert!(err.starts_with("Missing --output"), "{err}");
+        let err = args(&["--input"]).err().unwrap();
+        assert!(err.starts_with("Missing value for --input"), "{err}");
+        let err = args(&["--input", "in.png", "--sepia", "-o", "out.png"])
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Unknown argument: --sepia"), "{err}");
+    }
+
+    #[test]
+    fn renders_a_toml_spec() {
+        let spec = parse_spec(
+            r##"
+            crop = { x1 = 0, y1 = 0, x2 = 24, y2 = 20 }
+
+            [[annotations]]
+            type = "rect"
+            rect = { x1 = 2, y1 = 2, x2 = 10, y2 = 10 }
+            color = "#ff0000"
+            width = 2
+            fill = "#ff0000"
+
+            [[annotations]]
+            type = "blur"
+            rect = { x1 = 12, y1 = 2, x2 = 20, y2 = 10 }
+            mode = "solid"
+            strength = 10
+            "##,
+            true,
+        )
+        .unwrap();
+        let pixbuf = render(background(), spec, true).unwrap();
+        assert_eq!((pixbuf.width(), pixbuf.height()), (24, 20));
+        assert_eq!(pixel(&pixbuf, 6, 6), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixbuf, 16, 6), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixbuf, 6, 16), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn renders_a_json_spec() {
+        let spec = parse_spec(
+            r##"{ "annotations": [
+                { "type": "blur", "rect": { "x1": 0, "y1": 0, "x2": 8, "y2": 8 }, "mode": "solid", "strength": 10 }
+            ] }"##,
+            false,
+        )
+        .unwrap();
+        let pixbuf = render(background(), spec, false).unwrap();
+        assert_eq!((pixbuf.width(), pixbuf.height()), (32, 32));
+        assert_eq!(pixel(&pixbuf, 4, 4), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixbuf, 20, 20), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = parse_spec(r#"{ "annotations": [], "zoom": 2 }"#, false)
+            .err()
+            .unwrap();
+        assert!(err.contains("unknown field `zoom`"), "{err}");
+        let err = parse_spec("annotation = []", true).err().unwrap();
+        assert!(err.contains("unknown field `annotation`"), "{err}");
+    }
+
+    #[test]
+    fn rejects_an_empty_crop() {
+        let spec = parse_spec(
+            r#"{ "crop": { "x1": 4, "y1": 4, "x2": 4, "y2": 20 } }"#,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            render(background(), spec, false).err().unwrap(),
+            "Crop rectangle is empty."
+        );
+    }
+}
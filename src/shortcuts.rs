@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use gtk::prelude::*;
+
+use crate::editor::Tool;
+
+pub struct Shortcut {
+    pub action: &'static str,
+    pub title: &'static str,
+    pub accels: &'static [&'static str],
+}
+
+pub struct Group {
+    pub title: &'static str,
+    pub shortcuts: &'static [Shortcut],
+}
+
+const fn shortcut(
+    action: &'static str,
+    title: &'static str,
+    accels: &'static [&'static str],
+) -> Shortcut {
+    Shortcut {
+        action,
+        title,
+        accels,
+    }
+}
+
+// Action names are the keys users remap under [shortcuts] in settings.toml; all live in the
+// "win." namespace.
+pub const GROUPS: &[Group] = &[
+    Group {
+        title: "General",
+        shortcuts: &[
+            shortcut("capture", "Capture screenshot", &["<Control>n"]),
+            shortcut("open", "Open image", &["<Control>o"]),
+            shortcut("paste", "Paste from clipboard", &["<Control>v"]),
+            shortcut("copy", "Copy to clipboard", &["<Control>c"]),
+            shortcut("save", "Save image", &["<Control>s"]),
+            shortcut("open-project", "Open project", &["<Control><Shift>o"]),
+            shortcut("save-project", "Save project", &["<Control><Shift>s"]),
+            shortcut("preferences", "Preferences", &["<Control>comma"]),
+            shortcut("shortcuts", "Keyboard shortcuts", &["<Control>question"]),
+        ],
+    },
+    Group {
+        title: "Editing",
+        shortcuts: &[
+            shortcut("undo", "Undo", &["<Control>z"]),
+            shortcut("redo", "Redo", &["<Control><Shift>z", "<Control>y"]),
+            shortcut("delete", "Delete", &["Delete", "BackSpace"]),
+            shortcut("duplicate", "Duplicate", &["<Control>d"]),
+            shortcut("bring-forward", "Bring forward", &["Page_Up"]),
+            shortcut("send-backward", "Send backward", &["Page_Down"]),
+            shortcut("bring-to-front", "Bring to front", &["Home"]),
+            shortcut("send-to-back", "Send to back", &["End"]),
+        ],
+    },
+    Group {
+        title: "Tools",
+        shortcuts: &[
+            shortcut("tool-select", "Select", &["s"]),
+            shortcut("tool-crop", "Crop", &["c"]),
+            shortcut("tool-pen", "Pen", &["p"]),
+            shortcut("tool-highlighter", "Highlighter", &["h"]),
+            shortcut("tool-rect", "Rectangle", &["r"]),
+            shortcut("tool-ellipse", "Ellipse", &["e"]),
+            shortcut("tool-line", "Line", &["l"]),
+            shortcut("tool-arrow", "Arrow", &["a"]),
+            shortcut("tool-text", "Text", &["t"]),
+            shortcut("tool-callout", "Callout", &["k"]),
+            shortcut("tool-step", "Step marker", &["n"]),
+            shortcut("tool-blur", "Blur", &["b"]),
+            shortcut("tool-magnify", "Magnify", &["m"]),
+            shortcut("tool-spotlight", "Spotlight", &["f"]),
+            shortcut("tool-eyedropper", "Pick color from image", &["i"]),
+        ],
+    },
+    Group {
+        title: "View",
+        shortcuts: &[
+            shortcut("zoom-fit", "Fit to window", &["<Control>0"]),
+            shortcut("zoom-reset", "Actual size", &["<Control>1"]),
+        ],
+    },
+];
+
+pub fn tool_action(tool: Tool) -> &'static str {
+    match tool {
+        Tool::Select => "tool-select",
+        Tool::Pen => "tool-pen",
+        Tool::Highlighter => "tool-highlighter",
+        Tool::Rect => "tool-rect",
+        Tool::Ellipse => "tool-ellipse",
+        Tool::Line => "tool-line",
+        Tool::Arrow => "tool-arrow",
+        Tool::Text => "tool-text",
+        Tool::Callout => "tool-callout",
+        Tool::Step => "tool-step",
+        Tool::Blur => "tool-blur",
+        Tool::Magnify => "tool-magnify",
+        Tool::Spotlight => "tool-spotlight",
+        Tool::Eyedropper => "tool-eyedropper",
+        Tool::Crop => "tool-crop",
+    }
+}
+
+// User bindings replace the defaults for an action entirely; an empty list unbinds it.
+// Accelerators GTK can't parse are dropped.
+pub fn accels(action: &str, overrides: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    resolve_accels(action, overrides, |accel| {
+        gtk::accelerator_parse(accel).is_some()
+    })
+}
+
+fn resolve_accels(
+    action: &str,
+    overrides: &BTreeMap<String, Vec<String>>,
+    parses: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let accels = match overrides.get(action) {
+        Some(accels) => accels.clone(),
+        None => GROUPS
+            .iter()
+            .flat_map(|group| group.shortcuts)
+            .find(|shortcut| shortcut.action == action)
+            .map(|shortcut| {
+                shortcut
+                    .accels
+                    .iter()
+                    .map(|accel| accel.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    accels.into_iter().filter(|accel| parses(accel)).collect()
+}
+
+pub fn unknown_actions(overrides: &BTreeMap<String, Vec<String>>) -> Vec<&str> {
+    overrides
+        .keys()
+        .map(String::as_str)
+        .filter(|action| {
+            !GROUPS
+                .iter()
+                .flat_map(|group| group.shortcuts)
+                .any(|shortcut| shortcut.action == *action)
+        })
+        .collect()
+}
+
+pub fn controller(overrides: &BTreeMap<String, Vec<String>>) -> gtk::ShortcutController {
+    let controller = gtk::ShortcutController::new();
+    for shortcut in GROUPS.iter().flat_map(|group| group.shortcuts) {
+        let accels = accels(shortcut.action, overrides);
+        if accels.is_empty() {
+            continue;
+        }
+        controller.add_shortcut(gtk::Shortcut::new(
+            gtk::ShortcutTrigger::parse_string(&accels.join("|")),
+            Some(gtk::NamedAction::new(&format!("win.{}", shortcut.action))),
+        ));
+    }
+    controller
+}
+
+// GtkShortcutsWindow is deprecated in GTK 4.18 but libadwaita's replacement needs 1.8.
+#[allow(deprecated)]
+pub fn window(
+    parent: &impl IsA<gtk::Window>,
+    overrides: &BTreeMap<String, Vec<String>>,
+) -> gtk::ShortcutsWindow {
+    let section = gtk::ShortcutsSection::builder()
+        .section_name("shortcuts")
+        .build();
+    for group in GROUPS {
+        let shortcuts_group = gtk::ShortcutsGroup::builder().title(group.title).build();
+        for shortcut in group.shortcuts {
+            let accels = accels(shortcut.action, overrides);
+            if accels.is_empty() {
+                continue;
+            }
+            shortcuts_group.add_shortcut(
+                &gtk::ShortcutsShortcut::builder()
+                    .title(shortcut.title)
+                    .accelerator(accels.join(" "))
+                    .build(),
+            );
+        }
+        section.add_group(&shortcuts_group);
+    }
+    // Listed so a typo in settings.toml is visible here, not just in a passing status message.
+    let unknown = unknown_actions(overrides);
+    if !unknown.is_empty() {
+        let shortcuts_group = gtk::ShortcutsGroup::builder()
+            .title("Unknown actions in settings")
+            .build();
+        for action in unknown {
+            shortcuts_group.add_shortcut(
+                &gtk::ShortcutsShortcut::builder()
+                    .title(action)
+                    .subtitle("Not a GreatShot action; this binding does nothing")
+                    .accelerator(accels(action, overrides).join(" "))
+                    .build(),
+            );
+        }
+        section.add_group(&shortcuts_group);
+    }
+    let window = gtk::ShortcutsWindow::builder()
+        .transient_for(parent)
+        .modal(true)
+        .build();
+    window.add_section(&section);
+    window
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // gtk::accelerator_parse needs an initialized GTK, so tests stand in a parser that
+    // rejects anything containing "bogus".
+    fn accels(action: &str, overrides: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+        resolve_accels(action, overrides, |accel| !accel.contains("bogus"))
+    }
+
+    fn overrides(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(action, accels)| {
+                let accels = accels.iter().map(|accel| accel.to_string()).collect();
+                (action.to_string(), accels)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn defaults_apply_without_an_override() {
+        assert_eq!(
+            accels("redo", &BTreeMap::new()),
+            ["<Control><Shift>z", "<Control>y"]
+        );
+        assert!(accels("no-such-action", &BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn an_override_replaces_the_defaults() {
+        let overrides = overrides(&[("redo", &["<Control>r"])]);
+        assert_eq!(accels("redo", &overrides), ["<Control>r"]);
+        assert_eq!(accels("undo", &overrides), ["<Control>z"]);
+    }
+
+    #[test]
+    fn an_empty_override_unbinds() {
+        let overrides = overrides(&[("tool-pen", &[])]);
+        assert!(accels("tool-pen", &overrides).is_empty());
+    }
+
+    #[test]
+    fn unparsable_accelerators_are_dropped() {
+        let overrides = overrides(&[("save", &["<bogus>s", "<Control>s"]), ("copy", &["bogus"])]);
+        assert_eq!(accels("save", &overrides), ["<Control>s"]);
+        assert!(accels("copy", &overrides).is_empty());
+    }
+
+    #[test]
+    fn unknown_actions_are_reported() {
+        let overrides = overrides(&[("save", &["<Control>s"]), ("sav", &["<Control>s"])]);
+        assert_eq!(unknown_actions(&overrides), ["sav"]);
+    }
+}
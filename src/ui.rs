@@ -1,18 +1,36 @@
 use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::io::Cursor;
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::mpsc;
 use std::sync::Arc;
+use std::sync::mpsc;
 use std::time::Duration;
 
 use adw::prelude::*;
-use ashpd::desktop::screenshot::Screenshot;
+use ashpd::desktop::Color;
+use gdk_pixbuf::Pixbuf;
 use gtk::gdk;
 use gtk::gio;
 use gtk::glib;
-use gdk_pixbuf::Pixbuf;
 
-use crate::editor::{self, Annotation, EditorState, Point, Rect, Tool};
+use crate::editor::{
+    self, Annotation, BlurMode, BlurShape, EditorState, Point, Rect, SpotlightShape, TextEdit,
+    Tool, ZOrder,
+};
+use crate::export::{self, ImageFormat};
+use crate::history::Command;
+use crate::portal;
+use crate::preferences::{self, Preferences};
+use crate::project;
+use crate::redact;
+use crate::shortcuts;
+
+mod palettes;
+mod preferences_window;
+mod text_editor;
+
+use text_editor::TextEditor;
 
 const ICON_CAPTURE: &[u8] = include_bytes!("../assets/icons/camera.svg");
 const ICON_UNDO: &[u8] = include_bytes!("../assets/icons/arrow-back-up.svg");
@@ -22,6 +40,7 @@ const ICON_PALETTE: &[u8] = include_bytes!("../assets/icons/palette.svg");
 const ICON_OPEN: &[u8] = include_bytes!("../assets/icons/folder-open.svg");
 const ICON_PASTE: &[u8] = include_bytes!("../assets/icons/clipboard.svg");
 const ICON_SAVE: &[u8] = include_bytes!("../assets/icons/device-floppy.svg");
+const ICON_MENU: &[u8] = include_bytes!("../assets/icons/menu-2.svg");
 const ICON_SETTINGS: &[u8] = include_bytes!("../assets/icons/settings.svg");
 const ICON_CLOCK: &[u8] = include_bytes!("../assets/icons/clock.svg");
 const ICON_POINTER: &[u8] = include_bytes!("../assets/icons/pointer.svg");
@@ -29,11 +48,22 @@ const ICON_ZOOM: &[u8] = include_bytes!("../assets/icons/zoom-in.svg");
 const ICON_SELECT: &[u8] = include_bytes!("../assets/icons/select.svg");
 const ICON_CROP: &[u8] = include_bytes!("../assets/icons/crop.svg");
 const ICON_PEN: &[u8] = include_bytes!("../assets/icons/pencil.svg");
+const ICON_HIGHLIGHTER: &[u8] = include_bytes!("../assets/icons/highlight.svg");
 const ICON_RECT: &[u8] = include_bytes!("../assets/icons/square.svg");
+const ICON_ELLIPSE: &[u8] = include_bytes!("../assets/icons/circle.svg");
+const ICON_FILL: &[u8] = include_bytes!("../assets/icons/paint.svg");
 const ICON_LINE: &[u8] = include_bytes!("../assets/icons/minus.svg");
 const ICON_ARROW: &[u8] = include_bytes!("../assets/icons/arrow-right.svg");
 const ICON_TEXT: &[u8] = include_bytes!("../assets/icons/text-size.svg");
+const ICON_STEP: &[u8] = include_bytes!("../assets/icons/list-numbers.svg");
+const ICON_CALLOUT: &[u8] = include_bytes!("../assets/icons/message.svg");
 const ICON_BLUR: &[u8] = include_bytes!("../assets/icons/blur.svg");
+const ICON_MAGNIFY: &[u8] = include_bytes!("../assets/icons/zoom-in-area.svg");
+const ICON_SPOTLIGHT: &[u8] = include_bytes!("../assets/icons/focus-centered.svg");
+const ICON_EYEDROPPER: &[u8] = include_bytes!("../assets/icons/color-picker.svg");
+const ICON_SWATCHES: &[u8] = include_bytes!("../assets/icons/color-swatch.svg");
+const ICON_SCREEN_COLOR: &[u8] = include_bytes!("../assets/icons/device-desktop.svg");
+const ICON_SECURE: &[u8] = include_bytes!("../assets/icons/shield-lock.svg");
 
 fn set_image_from_svg(image: &gtk::Image, icon: &[u8], color: &str) {
     let svg = String::from_utf8_lossy(icon).replace("#e6e6e6", color);
@@ -44,9 +74,11 @@ fn set_image_from_svg(image: &gtk::Image, icon: &[u8], color: &str) {
     image.set_paintable(paintable.as_ref());
 }
 
+type IconImages = Rc<RefCell<Vec<(gtk::Image, &'static [u8])>>>;
+
 fn create_icon(
     icon: &'static [u8],
-    icon_images: &Rc<RefCell<Vec<(gtk::Image, &'static [u8])>>>,
+    icon_images: &IconImages,
     icon_color: &Rc<RefCell<String>>,
 ) -> gtk::Image {
     let image = gtk::Image::new();
@@ -63,21 +95,197 @@ fn create_icon(
     image
 }
 
-pub fn build_ui(app: &adw::Application) {
-    let runtime = Arc::new(
-        tokio::runtime::Runtime::new().expect("Failed to start async runtime"),
-    );
+#[derive(Default)]
+pub struct CaptureOptions {
+    pub delay: f64,
+    pub interactive: bool,
+    pub output: Option<PathBuf>,
+    pub clipboard: bool,
+    pub quit_after: bool,
+    pub command_line: Option<gio::ApplicationCommandLine>,
+    // Keeps the application running until the capture has been saved or has failed.
+    pub hold: Option<gio::ApplicationHoldGuard>,
+}
+
+#[derive(Clone)]
+pub struct EditorWindow {
+    pub window: adw::ApplicationWindow,
+    open_file: Rc<dyn Fn(&gio::File)>,
+    capture: Rc<dyn Fn(CaptureOptions)>,
+    delay_spin: gtk::SpinButton,
+    interactive_toggle: gtk::Switch,
+    preferences_paused: Rc<Cell<bool>>,
+    capture_failed: Rc<Cell<bool>>,
+}
+
+impl EditorWindow {
+    pub fn present(&self) {
+        self.window.present();
+    }
+
+    pub fn open(&self, file: &gio::File) {
+        (self.open_file)(file);
+    }
+
+    pub fn capture(&self, options: CaptureOptions) {
+        (self.capture)(options);
+    }
+
+    // Command-line overrides apply to this run only and aren't written back to the settings.
+    pub fn set_capture_defaults(&self, delay: Option<f64>, interactive: Option<bool>) {
+        self.preferences_paused.set(true);
+        if let Some(delay) = delay {
+            self.delay_spin.set_value(delay);
+        }
+        if let Some(interactive) = interactive {
+            self.interactive_toggle.set_active(interactive);
+        }
+        self.preferences_paused.set(false);
+    }
+
+    pub fn capture_delay(&self) -> f64 {
+        self.delay_spin.value()
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.interactive_toggle.is_active()
+    }
+
+    // GApplication ignores a local command line's exit status once the main loop has run,
+    // so main() checks this after the application exits.
+    pub fn capture_failed(&self) -> bool {
+        self.capture_failed.get()
+    }
+}
+
+fn fail_command_line(command_line: &gio::ApplicationCommandLine, msg: &str, failed: &Cell<bool>) {
+    command_line.printerr_literal(&format!("{msg}\n"));
+    command_line.set_exit_status(1);
+    if !command_line.is_remote() {
+        failed.set(true);
+    }
+}
+
+fn copy_to_clipboard(pixbuf: &Pixbuf) -> bool {
+    let Some(display) = gdk::Display::default() else {
+        return false;
+    };
+    let texture = gdk::Texture::for_pixbuf(pixbuf);
+    display.clipboard().set_texture(&texture);
+    true
+}
+
+fn finish_capture(
+    state: &EditorState,
+    options: &CaptureOptions,
+    preferences: &Preferences,
+) -> Option<String> {
+    if options.output.is_none() && !options.clipboard {
+        return None;
+    }
+    let pixbuf = match editor::export_pixbuf(state) {
+        Ok(pixbuf) => pixbuf,
+        Err(err) => return Some(err),
+    };
+    if let Some(path) = options.output.clone() {
+        let saved =
+            export::resolve_path(path, preferences.export_format).and_then(|(path, format)| {
+                export::save(&pixbuf, &path, format, &preferences.save_options())
+            });
+        if let Err(err) = saved {
+            return Some(format!("Save failed: {err}"));
+        }
+    }
+    if options.clipboard && !copy_to_clipboard(&pixbuf) {
+        return Some("Clipboard unavailable.".to_string());
+    }
+    None
+}
+
+fn image_filter(format: ImageFormat) -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some(format.label()));
+    for extension in format.extensions() {
+        filter.add_suffix(extension);
+    }
+    filter
+}
+
+fn filter_format(filter: &gtk::FileFilter) -> Option<ImageFormat> {
+    let name = filter.name()?;
+    ImageFormat::ALL
+        .into_iter()
+        .find(|format| format.label() == name.as_str())
+}
+
+const MAIN_MENU: &[(&str, &str)] = &[
+    ("Open Project…", "open-project"),
+    ("Save Project…", "save-project"),
+    ("Preferences", "preferences"),
+    ("Keyboard Shortcuts", "shortcuts"),
+];
+
+const CONTEXT_MENU: &[(&str, &str)] = &[
+    ("Delete", "delete"),
+    ("Duplicate", "duplicate"),
+    ("Bring Forward", "bring-forward"),
+    ("Send Backward", "send-backward"),
+    ("Bring to Front", "bring-to-front"),
+    ("Send to Back", "send-to-back"),
+    ("Blur Annotations Below", "blur-include-below"),
+];
+
+// Rebuilt whenever the shortcuts change, since menu items show their accelerator.
+fn fill_menu(menu: &gio::Menu, items: &[(&str, &str)], overrides: &BTreeMap<String, Vec<String>>) {
+    menu.remove_all();
+    for (label, action) in items {
+        let item = gio::MenuItem::new(Some(label), Some(&format!("win.{action}")));
+        if let Some(accel) = shortcuts::accels(action, overrides).first() {
+            item.set_attribute_value("accel", Some(&accel.to_variant()));
+        }
+        menu.append_item(&item);
+    }
+}
+
+fn project_filter() -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("GreatShot projects"));
+    filter.add_suffix(project::PROJECT_EXTENSION);
+    filter
+}
+
+type UpdatePreferences = Rc<dyn Fn(&dyn Fn(&mut Preferences))>;
+
+enum PortalResult {
+    Capture(Result<String, String>),
+    Color(Result<Color, String>),
+}
+
+pub fn build_ui(app: &adw::Application) -> EditorWindow {
+    let runtime = Arc::new(tokio::runtime::Runtime::new().expect("Failed to start async runtime"));
+
+    let (loaded, preferences_error) = match preferences::load() {
+        Ok(preferences) => (preferences, None),
+        Err(err) => (Preferences::default(), Some(err)),
+    };
+    let preferences_writable = preferences_error.is_none();
+    let preferences = Rc::new(RefCell::new(loaded));
+    // Held while startup applies the loaded values so they aren't immediately written back.
+    let preferences_paused = Rc::new(Cell::new(true));
+    let initial = preferences.borrow().clone();
 
     let state = Rc::new(RefCell::new(EditorState::new()));
     {
         let mut state = state.borrow_mut();
-        state.color = gdk::RGBA::new(1.0, 0.30, 0.30, 1.0);
-        state.fit_to_window = true;
+        state.color = initial.color;
+        state.stroke_width = initial.stroke_width;
+        state.text_size = initial.text_size;
+        state.secure_export = initial.secure_export;
+        state.fit_to_window = initial.fit_to_window;
         state.zoom = 1.0;
     }
 
-    let icon_images: Rc<RefCell<Vec<(gtk::Image, &'static [u8])>>> =
-        Rc::new(RefCell::new(Vec::new()));
+    let icon_images: IconImages = Rc::new(RefCell::new(Vec::new()));
     let icon_color = Rc::new(RefCell::new(String::new()));
 
     if let Some(display) = gdk::Display::default() {
@@ -102,14 +310,7 @@ pub fn build_ui(app: &adw::Application) {
                      .color-swatch { min-width: 20px; min-height: 20px; border-radius: 999px; border: 2px solid rgba(255,255,255,0.18); }
                      .color-swatch.toggle:checked { border: 2px solid rgba(255,255,255,0.9); }
                      .color-custom { min-width: 20px; min-height: 20px; border-radius: 999px; border: 2px solid rgba(255,255,255,0.25); background: rgba(255,255,255,0.08); }
-                     .color-black { background: #1b1b1b; }
-                     .color-white { background: #f5f5f5; }
-                     .color-red { background: #ff4d4d; }
-                     .color-orange { background: #ff9f1a; }
-                     .color-yellow { background: #ffd93d; }
-                     .color-green { background: #3ddc84; }
-                     .color-blue { background: #3b82f6; }
-                     .color-purple { background: #8b5cf6; }
+                     .color-custom.toggle:checked { border: 2px solid rgba(255,255,255,0.9); }
                      .editor-canvas { background: #1e1e1e; }
                      .editor-status { color: #c9c9c9; font-size: 11px; }",
                 );
@@ -123,14 +324,7 @@ pub fn build_ui(app: &adw::Application) {
                      .color-swatch { min-width: 20px; min-height: 20px; border-radius: 999px; border: 2px solid rgba(0,0,0,0.2); }
                      .color-swatch.toggle:checked { border: 2px solid rgba(0,0,0,0.8); }
                      .color-custom { min-width: 20px; min-height: 20px; border-radius: 999px; border: 2px solid rgba(0,0,0,0.25); background: rgba(0,0,0,0.04); }
-                     .color-black { background: #1b1b1b; }
-                     .color-white { background: #f5f5f5; }
-                     .color-red { background: #ff4d4d; }
-                     .color-orange { background: #ff9f1a; }
-                     .color-yellow { background: #ffd93d; }
-                     .color-green { background: #3ddc84; }
-                     .color-blue { background: #3b82f6; }
-                     .color-purple { background: #8b5cf6; }
+                     .color-custom.toggle:checked { border: 2px solid rgba(0,0,0,0.8); }
                      .editor-canvas { background: #f4f4f4; }
                      .editor-status { color: #5c5c5c; font-size: 11px; }",
                 );
@@ -156,21 +350,24 @@ pub fn build_ui(app: &adw::Application) {
     let capture_button = gtk::Button::builder()
         .child(&create_icon(ICON_CAPTURE, &icon_images, &icon_color))
         .tooltip_text("Capture screenshot")
+        .action_name("win.capture")
         .build();
     header.pack_start(&capture_button);
 
     let open_button = gtk::Button::builder()
         .child(&create_icon(ICON_OPEN, &icon_images, &icon_color))
         .tooltip_text("Open image")
+        .action_name("win.open")
         .build();
     let paste_button = gtk::Button::builder()
         .child(&create_icon(ICON_PASTE, &icon_images, &icon_color))
         .tooltip_text("Paste from clipboard")
+        .action_name("win.paste")
         .build();
     header.pack_start(&open_button);
     header.pack_start(&paste_button);
 
-    let delay_adjustment = gtk::Adjustment::new(0.0, 0.0, 10.0, 0.5, 1.0, 0.0);
+    let delay_adjustment = gtk::Adjustment::new(initial.capture_delay, 0.0, 10.0, 0.5, 1.0, 0.0);
     let delay_spin = gtk::SpinButton::builder()
         .adjustment(&delay_adjustment)
         .digits(1)
@@ -180,7 +377,7 @@ pub fn build_ui(app: &adw::Application) {
         .build();
     let interactive_toggle = gtk::Switch::builder()
         .tooltip_text("Interactive capture")
-        .active(true)
+        .active(initial.interactive)
         .build();
     let settings_button = gtk::MenuButton::builder()
         .child(&create_icon(ICON_SETTINGS, &icon_images, &icon_color))
@@ -194,7 +391,7 @@ pub fn build_ui(app: &adw::Application) {
         .margin_start(10)
         .margin_end(10)
         .build();
-    let size_adjustment = gtk::Adjustment::new(4.0, 1.0, 32.0, 1.0, 2.0, 0.0);
+    let size_adjustment = gtk::Adjustment::new(initial.stroke_width, 1.0, 32.0, 1.0, 2.0, 0.0);
     let size_spin = gtk::SpinButton::builder()
         .adjustment(&size_adjustment)
         .climb_rate(1.0)
@@ -203,6 +400,88 @@ pub fn build_ui(app: &adw::Application) {
         .width_chars(2)
         .tooltip_text("Stroke size")
         .build();
+    let fill_switch = gtk::Switch::builder()
+        .tooltip_text("Fill shapes")
+        .valign(gtk::Align::Center)
+        .build();
+    let fill_color_button = gtk::ColorDialogButton::builder()
+        .dialog(
+            &gtk::ColorDialog::builder()
+                .title("Fill Color")
+                .with_alpha(false)
+                .build(),
+        )
+        .rgba(&gdk::RGBA::new(1.0, 0.30, 0.30, 1.0))
+        .tooltip_text("Fill color")
+        .build();
+    let fill_opacity_adjustment = gtk::Adjustment::new(0.35, 0.05, 1.0, 0.05, 0.1, 0.0);
+    let fill_opacity_scale = gtk::Scale::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .adjustment(&fill_opacity_adjustment)
+        .draw_value(false)
+        .width_request(80)
+        .tooltip_text("Fill opacity")
+        .build();
+    let blur_mode_dropdown = gtk::DropDown::from_strings(&["Pixelate", "Gaussian blur", "Solid"]);
+    blur_mode_dropdown.set_tooltip_text(Some("Redaction mode"));
+    let blur_shape_dropdown = gtk::DropDown::from_strings(&["Rectangle", "Brush", "Lasso"]);
+    blur_shape_dropdown.set_tooltip_text(Some("Redaction shape"));
+    let blur_strength_adjustment = gtk::Adjustment::new(10.0, 2.0, 64.0, 1.0, 4.0, 0.0);
+    let blur_strength_spin = gtk::SpinButton::builder()
+        .adjustment(&blur_strength_adjustment)
+        .climb_rate(1.0)
+        .digits(0)
+        .numeric(true)
+        .width_chars(2)
+        .tooltip_text("Redaction strength")
+        .build();
+    let blur_below_switch = gtk::Switch::builder()
+        .tooltip_text("New blurs also obscure annotations beneath them")
+        .valign(gtk::Align::Center)
+        .build();
+    let magnify_connectors_switch = gtk::Switch::builder()
+        .tooltip_text("Draw lines from new magnifiers back to their source")
+        .active(true)
+        .valign(gtk::Align::Center)
+        .build();
+    let spotlight_shape_dropdown = gtk::DropDown::from_strings(&["Rectangle", "Ellipse"]);
+    spotlight_shape_dropdown.set_tooltip_text(Some("Spotlight cut-out shape"));
+    let spotlight_opacity_adjustment = gtk::Adjustment::new(0.6, 0.1, 0.95, 0.05, 0.1, 0.0);
+    let spotlight_opacity_scale = gtk::Scale::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .adjustment(&spotlight_opacity_adjustment)
+        .draw_value(false)
+        .width_request(80)
+        .tooltip_text("Dim opacity")
+        .build();
+    let secure_switch = gtk::Switch::builder()
+        .tooltip_text(
+            "Bake redactions irreversibly as blocks of at least 16px and strip metadata on export",
+        )
+        .active(initial.secure_export)
+        .valign(gtk::Align::Center)
+        .build();
+    let text_size_adjustment = gtk::Adjustment::new(initial.text_size, 6.0, 200.0, 1.0, 4.0, 0.0);
+    let text_size_spin = gtk::SpinButton::builder()
+        .adjustment(&text_size_adjustment)
+        .climb_rate(1.0)
+        .digits(0)
+        .numeric(true)
+        .width_chars(3)
+        .tooltip_text("Text size")
+        .build();
+    let font_button = gtk::FontDialogButton::builder()
+        .dialog(
+            &gtk::FontDialog::builder()
+                .title("Text Font")
+                .modal(true)
+                .build(),
+        )
+        .level(gtk::FontLevel::Face)
+        .use_font(true)
+        .font_desc(&editor::TextFont::default().description(initial.text_size))
+        .tooltip_text("Text font")
+        .build();
     let zoom_adjustment = gtk::Adjustment::new(1.0, 0.25, 3.0, 0.05, 0.1, 0.0);
     let zoom_scale = gtk::Scale::builder()
         .orientation(gtk::Orientation::Horizontal)
@@ -213,7 +492,7 @@ pub fn build_ui(app: &adw::Application) {
         .tooltip_text("Zoom")
         .build();
     let fit_toggle = gtk::ToggleButton::with_label("Fit");
-    fit_toggle.set_active(true);
+    fit_toggle.set_active(initial.fit_to_window);
     let zoom_reset = gtk::Button::with_label("100%");
     let size_row = gtk::Box::builder()
         .orientation(gtk::Orientation::Horizontal)
@@ -231,6 +510,130 @@ pub fn build_ui(app: &adw::Application) {
         .build();
     size_group.append(&size_icon);
     size_group.append(&size_row);
+    let fill_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let fill_label = gtk::Label::new(Some("Fill"));
+    fill_label.set_xalign(0.0);
+    fill_label.set_hexpand(true);
+    fill_row.append(&fill_label);
+    fill_row.append(&fill_opacity_scale);
+    fill_row.append(&fill_color_button);
+    fill_row.append(&fill_switch);
+    let fill_icon = create_icon(ICON_FILL, &icon_images, &icon_color);
+    let fill_group = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    fill_group.append(&fill_icon);
+    fill_group.append(&fill_row);
+    let blur_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let blur_label = gtk::Label::new(Some("Redact"));
+    blur_label.set_xalign(0.0);
+    blur_label.set_hexpand(true);
+    blur_row.append(&blur_label);
+    blur_row.append(&blur_shape_dropdown);
+    blur_row.append(&blur_mode_dropdown);
+    blur_row.append(&blur_strength_spin);
+    let blur_icon = create_icon(ICON_BLUR, &icon_images, &icon_color);
+    let blur_group = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    blur_group.append(&blur_icon);
+    blur_group.append(&blur_row);
+    let blur_below_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .margin_start(24)
+        .build();
+    let blur_below_label = gtk::Label::new(Some("Blur annotations below"));
+    blur_below_label.set_xalign(0.0);
+    blur_below_label.set_hexpand(true);
+    blur_below_row.append(&blur_below_label);
+    blur_below_row.append(&blur_below_switch);
+    // Secure export can't keep gaussian blurs or small blocks, so say so next to the settings.
+    let blur_secure_note = gtk::Label::builder()
+        .label(format!(
+            "Secure export saves this as noisy {}px blocks, so the file won't match the canvas.",
+            redact::MIN_BLOCK_SIZE
+        ))
+        .xalign(0.0)
+        .wrap(true)
+        .max_width_chars(36)
+        .margin_start(24)
+        .build();
+    blur_secure_note.add_css_class("dim-label");
+    let magnify_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let magnify_label = gtk::Label::new(Some("Magnifier connectors"));
+    magnify_label.set_xalign(0.0);
+    magnify_label.set_hexpand(true);
+    magnify_row.append(&magnify_label);
+    magnify_row.append(&magnify_connectors_switch);
+    let magnify_icon = create_icon(ICON_MAGNIFY, &icon_images, &icon_color);
+    let magnify_group = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    magnify_group.append(&magnify_icon);
+    magnify_group.append(&magnify_row);
+    let spotlight_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let spotlight_label = gtk::Label::new(Some("Spotlight"));
+    spotlight_label.set_xalign(0.0);
+    spotlight_label.set_hexpand(true);
+    spotlight_row.append(&spotlight_label);
+    spotlight_row.append(&spotlight_opacity_scale);
+    spotlight_row.append(&spotlight_shape_dropdown);
+    let spotlight_icon = create_icon(ICON_SPOTLIGHT, &icon_images, &icon_color);
+    let spotlight_group = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    spotlight_group.append(&spotlight_icon);
+    spotlight_group.append(&spotlight_row);
+    let secure_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let secure_label = gtk::Label::new(Some("Secure export"));
+    secure_label.set_xalign(0.0);
+    secure_label.set_hexpand(true);
+    secure_row.append(&secure_label);
+    secure_row.append(&secure_switch);
+    let secure_icon = create_icon(ICON_SECURE, &icon_images, &icon_color);
+    let secure_group = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    secure_group.append(&secure_icon);
+    secure_group.append(&secure_row);
+    let text_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let text_label = gtk::Label::new(Some("Text"));
+    text_label.set_xalign(0.0);
+    text_label.set_hexpand(true);
+    text_row.append(&text_label);
+    text_row.append(&font_button);
+    text_row.append(&text_size_spin);
+    let text_icon = create_icon(ICON_TEXT, &icon_images, &icon_color);
+    let text_group = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    text_group.append(&text_icon);
+    text_group.append(&text_row);
     let delay_row = gtk::Box::builder()
         .orientation(gtk::Orientation::Horizontal)
         .spacing(8)
@@ -289,6 +692,14 @@ pub fn build_ui(app: &adw::Application) {
     let divider2 = gtk::Separator::new(gtk::Orientation::Horizontal);
     let divider3 = gtk::Separator::new(gtk::Orientation::Horizontal);
     settings_box.append(&size_group);
+    settings_box.append(&fill_group);
+    settings_box.append(&text_group);
+    settings_box.append(&blur_group);
+    settings_box.append(&blur_below_row);
+    settings_box.append(&blur_secure_note);
+    settings_box.append(&magnify_group);
+    settings_box.append(&spotlight_group);
+    settings_box.append(&secure_group);
     settings_box.append(&divider1);
     settings_box.append(&zoom_group);
     settings_box.append(&divider2);
@@ -300,23 +711,37 @@ pub fn build_ui(app: &adw::Application) {
     settings_popover.set_child(Some(&settings_box));
     settings_button.set_popover(Some(&settings_popover));
 
+    // Both menus are filled in once the shortcuts are applied below.
+    let main_menu = gio::Menu::new();
+    let context_menu = gio::Menu::new();
+    let menu_button = gtk::MenuButton::builder()
+        .child(&create_icon(ICON_MENU, &icon_images, &icon_color))
+        .menu_model(&main_menu)
+        .tooltip_text("Main menu")
+        .build();
+
+    header.pack_end(&menu_button);
     header.pack_end(&settings_button);
 
     let undo_button = gtk::Button::builder()
         .child(&create_icon(ICON_UNDO, &icon_images, &icon_color))
         .tooltip_text("Undo")
+        .action_name("win.undo")
         .build();
     let redo_button = gtk::Button::builder()
         .child(&create_icon(ICON_REDO, &icon_images, &icon_color))
         .tooltip_text("Redo")
+        .action_name("win.redo")
         .build();
     let copy_button = gtk::Button::builder()
         .child(&create_icon(ICON_COPY, &icon_images, &icon_color))
         .tooltip_text("Copy to clipboard")
+        .action_name("win.copy")
         .build();
     let save_button = gtk::Button::builder()
         .child(&create_icon(ICON_SAVE, &icon_images, &icon_color))
-        .tooltip_text("Save as PNG")
+        .tooltip_text("Save image")
+        .action_name("win.save")
         .build();
     header.pack_end(&copy_button);
     header.pack_end(&save_button);
@@ -336,6 +761,26 @@ pub fn build_ui(app: &adw::Application) {
         }
     });
 
+    let update_preferences: UpdatePreferences = {
+        let preferences = preferences.clone();
+        let preferences_paused = preferences_paused.clone();
+        let set_status = set_status.clone();
+        Rc::new(move |change| {
+            if preferences_paused.get() {
+                return;
+            }
+            let mut preferences = preferences.borrow_mut();
+            change(&mut preferences);
+            if !preferences_writable {
+                return;
+            }
+            if let Err(err) = preferences::save(&preferences) {
+                let msg = format!("Failed to save preferences: {err}");
+                set_status(&msg);
+            }
+        })
+    };
+
     let drawing_area = gtk::DrawingArea::builder()
         .content_width(900)
         .content_height(600)
@@ -344,10 +789,16 @@ pub fn build_ui(app: &adw::Application) {
     drawing_area.set_vexpand(true);
     drawing_area.add_css_class("editor-canvas");
 
+    let text_editor = TextEditor::new(&state, &drawing_area);
+
+    let canvas_overlay = gtk::Overlay::new();
+    canvas_overlay.set_child(Some(&drawing_area));
+    canvas_overlay.add_overlay(text_editor.view());
+
     let scroller = gtk::ScrolledWindow::builder()
         .hscrollbar_policy(gtk::PolicyType::Automatic)
         .vscrollbar_policy(gtk::PolicyType::Automatic)
-        .child(&drawing_area)
+        .child(&canvas_overlay)
         .build();
 
     let content = gtk::Box::builder()
@@ -391,6 +842,45 @@ pub fn build_ui(app: &adw::Application) {
         .tooltip_text("Custom color")
         .build();
     color_button.add_css_class("color-custom");
+    let screen_color_button = gtk::Button::builder()
+        .child(&create_icon(ICON_SCREEN_COLOR, &icon_images, &icon_color))
+        .tooltip_text("Pick color from screen")
+        .build();
+    screen_color_button.add_css_class("color-custom");
+    let eyedropper_color: Rc<Cell<Option<gdk::RGBA>>> = Rc::new(Cell::new(None));
+    let eyedropper_preview = gtk::DrawingArea::builder()
+        .content_width(20)
+        .content_height(20)
+        .valign(gtk::Align::Center)
+        .visible(false)
+        .build();
+    {
+        let eyedropper_color = eyedropper_color.clone();
+        eyedropper_preview.set_draw_func(move |_, ctx, width, height| {
+            let Some(color) = eyedropper_color.get() else {
+                return;
+            };
+            let radius = width.min(height) as f64 / 2.0;
+            ctx.arc(
+                width as f64 / 2.0,
+                height as f64 / 2.0,
+                radius - 1.0,
+                0.0,
+                std::f64::consts::TAU,
+            );
+            ctx.set_source_rgb(
+                color.red() as f64,
+                color.green() as f64,
+                color.blue() as f64,
+            );
+            let _ = ctx.fill_preserve();
+            ctx.set_source_rgba(0.5, 0.5, 0.5, 0.6);
+            ctx.set_line_width(1.0);
+            let _ = ctx.stroke();
+        });
+    }
+    let eyedropper_label = gtk::Label::builder().width_chars(7).visible(false).build();
+    eyedropper_label.add_css_class("monospace");
 
     let make_tool_button = |icon: &'static [u8], tooltip: &str| {
         let image = create_icon(icon, &icon_images, &icon_color);
@@ -400,18 +890,40 @@ pub fn build_ui(app: &adw::Application) {
         button
     };
 
-    let tool_buttons: Vec<(Tool, gtk::ToggleButton)> = vec![
+    let tool_buttons: Rc<Vec<(Tool, gtk::ToggleButton)>> = Rc::new(vec![
         (Tool::Select, make_tool_button(ICON_SELECT, "Select")),
         (Tool::Crop, make_tool_button(ICON_CROP, "Crop")),
         (Tool::Pen, make_tool_button(ICON_PEN, "Pen")),
+        (
+            Tool::Highlighter,
+            make_tool_button(ICON_HIGHLIGHTER, "Highlighter (Shift for straight lines)"),
+        ),
         (Tool::Rect, make_tool_button(ICON_RECT, "Rectangle")),
+        (Tool::Ellipse, make_tool_button(ICON_ELLIPSE, "Ellipse")),
         (Tool::Line, make_tool_button(ICON_LINE, "Line")),
         (Tool::Arrow, make_tool_button(ICON_ARROW, "Arrow")),
         (Tool::Text, make_tool_button(ICON_TEXT, "Text")),
+        (Tool::Callout, make_tool_button(ICON_CALLOUT, "Callout")),
+        (Tool::Step, make_tool_button(ICON_STEP, "Step marker")),
         (Tool::Blur, make_tool_button(ICON_BLUR, "Blur")),
-    ];
+        (Tool::Magnify, make_tool_button(ICON_MAGNIFY, "Magnify")),
+        (
+            Tool::Spotlight,
+            make_tool_button(ICON_SPOTLIGHT, "Spotlight"),
+        ),
+        (
+            Tool::Eyedropper,
+            make_tool_button(ICON_EYEDROPPER, "Pick color from image"),
+        ),
+    ]);
 
-    for (_, button) in tool_buttons.iter() {
+    // The eyedropper is a tool like any other but lives with the swatches it feeds.
+    for (tool, button) in tool_buttons.iter() {
+        if *tool == Tool::Eyedropper {
+            button.remove_css_class("tool-button");
+            button.add_css_class("color-custom");
+            continue;
+        }
         palette.append(button);
     }
 
@@ -423,14 +935,48 @@ pub fn build_ui(app: &adw::Application) {
 
     let window = adw::ApplicationWindow::builder()
         .application(app)
-        .default_width(1400)
-        .default_height(900)
+        .default_width(initial.window_width)
+        .default_height(initial.window_height)
         .title("GreatShot")
         .content(&toolbar_view)
         .build();
 
-    window.maximize();
-    window.present();
+    if initial.maximized {
+        window.maximize();
+    }
+    {
+        let update_preferences = update_preferences.clone();
+        window.connect_close_request(move |window| {
+            let (width, height) = window.default_size();
+            let maximized = window.is_maximized();
+            update_preferences(&|preferences| {
+                preferences.maximized = maximized;
+                if width > 0 && height > 0 {
+                    preferences.window_width = width;
+                    preferences.window_height = height;
+                }
+            });
+            glib::Propagation::Proceed
+        });
+    }
+
+    let palettes::Palettes {
+        button: palette_button,
+        swatch_box,
+        recent_box,
+        recent_separator,
+        selected_swatch,
+        refresh: refresh_palettes,
+        remember_color,
+        error: palette_error,
+    } = palettes::build(
+        &window,
+        &state,
+        set_status.clone(),
+        &update_preferences,
+        &icon_images,
+        &icon_color,
+    );
 
     let zoom_updating = Rc::new(Cell::new(false));
     let fit_updating = Rc::new(Cell::new(false));
@@ -438,6 +984,7 @@ pub fn build_ui(app: &adw::Application) {
     let apply_background = {
         let drawing_area = drawing_area.clone();
         let state = state.clone();
+        let preferences = preferences.clone();
         let zoom_adjustment = zoom_adjustment.clone();
         let fit_toggle = fit_toggle.clone();
         let zoom_updating = zoom_updating.clone();
@@ -447,14 +994,15 @@ pub fn build_ui(app: &adw::Application) {
             let height = pixbuf.height();
             drawing_area.set_content_width(width);
             drawing_area.set_content_height(height);
+            let fit = preferences.borrow().fit_to_window;
             {
                 let mut state = state.borrow_mut();
                 state.set_background(pixbuf);
-                state.fit_to_window = true;
+                state.fit_to_window = fit;
                 state.zoom = 1.0;
             }
             fit_updating.set(true);
-            fit_toggle.set_active(true);
+            fit_toggle.set_active(fit);
             fit_updating.set(false);
             zoom_updating.set(true);
             zoom_adjustment.set_value(1.0);
@@ -463,16 +1011,51 @@ pub fn build_ui(app: &adw::Application) {
         })
     };
 
-    let (sender, receiver) = mpsc::channel::<Result<String, String>>();
+    let capture_action = gio::SimpleAction::new("capture", None);
+    let (sender, receiver) = mpsc::channel::<PortalResult>();
+    let pending_capture: Rc<RefCell<Option<CaptureOptions>>> = Rc::new(RefCell::new(None));
+    let capture_failed = Rc::new(Cell::new(false));
 
     let set_status_for_timer = set_status.clone();
-    let button_for_timer = capture_button.clone();
+    let capture_action_for_timer = capture_action.clone();
     let apply_background_for_timer = apply_background.clone();
     let window_for_timer = window.clone();
+    let state_for_timer = state.clone();
+    let pending_for_timer = pending_capture.clone();
+    let capture_failed_for_timer = capture_failed.clone();
+    let screen_color_for_timer = screen_color_button.clone();
+    let remember_color_for_timer = remember_color.clone();
+    let preferences_for_timer = preferences.clone();
 
     glib::timeout_add_local(Duration::from_millis(100), move || {
-        while let Ok(result) = receiver.try_recv() {
-            button_for_timer.set_sensitive(true);
+        while let Ok(message) = receiver.try_recv() {
+            let result = match message {
+                PortalResult::Capture(result) => result,
+                PortalResult::Color(result) => {
+                    screen_color_for_timer.set_sensitive(true);
+                    match result {
+                        Ok(color) => {
+                            let color = gdk::RGBA::new(
+                                color.red() as f32,
+                                color.green() as f32,
+                                color.blue() as f32,
+                                1.0,
+                            );
+                            remember_color_for_timer(color);
+                            let msg = format!("Picked {}.", editor::hex_color(&color));
+                            set_status_for_timer(&msg);
+                        }
+                        Err(err) => {
+                            let msg = format!("Color pick failed: {err}");
+                            set_status_for_timer(&msg);
+                        }
+                    }
+                    continue;
+                }
+            };
+            capture_action_for_timer.set_enabled(true);
+            let options = pending_for_timer.borrow_mut().take().unwrap_or_default();
+            let mut error = None;
             match result {
                 Ok(uri) => {
                     let msg = format!("Captured: {uri}");
@@ -484,61 +1067,113 @@ pub fn build_ui(app: &adw::Application) {
                                 apply_background_for_timer(pixbuf);
                             }
                             Err(err) => {
-                                let msg = format!("Failed to load image: {err}");
-                                set_status_for_timer(&msg);
+                                error = Some(format!("Failed to load image: {err}"));
                             }
                         },
                         None => {
-                            set_status_for_timer("Failed to resolve capture path.");
+                            error = Some("Failed to resolve capture path.".to_string());
                         }
                     }
                 }
                 Err(err) => {
-                    let msg = format!("Capture failed: {err}");
-                    set_status_for_timer(&msg);
+                    error = Some(format!("Capture failed: {err}"));
+                }
+            }
+            if error.is_none() {
+                error = finish_capture(
+                    &state_for_timer.borrow(),
+                    &options,
+                    &preferences_for_timer.borrow(),
+                );
+                if error.is_none() && options.output.is_some() {
+                    set_status_for_timer("Saved capture.");
                 }
             }
-            window_for_timer.present();
+            if let Some(msg) = error.as_deref() {
+                set_status_for_timer(msg);
+            }
+            if let (Some(command_line), Some(msg)) =
+                (options.command_line.as_ref(), error.as_deref())
+            {
+                fail_command_line(command_line, msg, &capture_failed_for_timer);
+            }
+            if options.quit_after {
+                window_for_timer.close();
+            } else {
+                window_for_timer.present();
+            }
+            drop(options.hold);
         }
         glib::ControlFlow::Continue
     });
 
-    let runtime = runtime.clone();
-    let set_status_for_capture = set_status.clone();
-    let button = capture_button.clone();
-    let delay_spin = delay_spin.clone();
-    let interactive_toggle = interactive_toggle.clone();
-    let window_for_capture = window.clone();
-
-    capture_button.connect_clicked(move |_| {
-        button.set_sensitive(false);
-        set_status_for_capture("Capturing via portal...");
-        window_for_capture.minimize();
-        window_for_capture.set_visible(false);
-
+    {
         let runtime = runtime.clone();
         let sender = sender.clone();
-        let delay = delay_spin.value();
-        let interactive = interactive_toggle.is_active();
-
-        runtime.spawn(async move {
-            let hide_delay = std::time::Duration::from_millis(200);
-            tokio::time::sleep(hide_delay).await;
-            if delay > 0.0 {
-                tokio::time::sleep(std::time::Duration::from_secs_f64(delay)).await;
+        let set_status = set_status.clone();
+        screen_color_button.connect_clicked(move |button| {
+            button.set_sensitive(false);
+            set_status("Picking color via portal...");
+            let sender = sender.clone();
+            runtime.spawn(async move {
+                let _ = sender.send(PortalResult::Color(portal::pick_color().await));
+            });
+        });
+    }
+
+    let start_capture: Rc<dyn Fn(CaptureOptions)> = {
+        let runtime = runtime.clone();
+        let set_status = set_status.clone();
+        let capture_action = capture_action.clone();
+        let window = window.clone();
+        let capture_failed = capture_failed.clone();
+        Rc::new(move |options: CaptureOptions| {
+            if pending_capture.borrow().is_some() {
+                if let Some(command_line) = options.command_line.as_ref() {
+                    fail_command_line(
+                        command_line,
+                        "A capture is already in progress.",
+                        &capture_failed,
+                    );
+                }
+                return;
             }
-            let result = Screenshot::request()
-                .interactive(interactive)
-                .modal(true)
-                .send()
-                .await
-                .and_then(|request| request.response())
-                .map(|response| response.uri().to_string())
-                .map_err(|err| err.to_string());
+            capture_action.set_enabled(false);
+            set_status("Capturing via portal...");
+            window.minimize();
+            window.set_visible(false);
+
+            let runtime = runtime.clone();
+            let sender = sender.clone();
+            let delay = options.delay;
+            let interactive = options.interactive;
+            *pending_capture.borrow_mut() = Some(options);
+
+            runtime.spawn(async move {
+                let hide_delay = std::time::Duration::from_millis(200);
+                tokio::time::sleep(hide_delay).await;
+                if delay > 0.0 {
+                    tokio::time::sleep(std::time::Duration::from_secs_f64(delay)).await;
+                }
+                let result = portal::capture(interactive).await;
+                let _ = sender.send(PortalResult::Capture(result));
+            });
+        })
+    };
 
-            let _ = sender.send(result);
+    {
+        let start_capture = start_capture.clone();
+        let delay_spin = delay_spin.clone();
+        let interactive_toggle = interactive_toggle.clone();
+        capture_action.connect_activate(move |_, _| {
+            start_capture(CaptureOptions {
+                delay: delay_spin.value(),
+                interactive: interactive_toggle.is_active(),
+                ..Default::default()
+            });
         });
-    });
+        window.add_action(&capture_action);
+    }
 
     let state_for_draw = state.clone();
     let draw_area_for_draw = drawing_area.clone();
@@ -563,14 +1198,23 @@ pub fn build_ui(app: &adw::Application) {
     {
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        drag.connect_drag_begin(move |_, x, y| {
+        drag.connect_drag_begin(move |gesture, x, y| {
+            let duplicate = gesture
+                .current_event_state()
+                .contains(gdk::ModifierType::ALT_MASK);
             let mut state = state.borrow_mut();
             let point_view = Point { x, y };
             let point = editor::map_to_image(&state, x, y);
             state.drag_start_view = Some(point_view);
             match state.tool {
                 Tool::Select => {
-                    state.selected = editor::hit_test(&state.annotations, point);
+                    state.drag_handle = editor::handle_at(&state, point);
+                    if state.drag_handle.is_none() {
+                        state.selected = editor::hit_test(&state.annotations, point);
+                        if duplicate {
+                            state.begin_duplicate_drag();
+                        }
+                    }
                     if let Some(index) = state.selected {
                         state.draft = None;
                         state.crop_rect = None;
@@ -587,15 +1231,24 @@ pub fn build_ui(app: &adw::Application) {
                         y2: point.y,
                     });
                 }
-                Tool::Pen => {
-                    state.draft = Some(Annotation::Pen {
-                        points: vec![point],
+                Tool::Pen | Tool::Highlighter => {
+                    state.draft = Some(state.stroke_annotation(point));
+                }
+                Tool::Rect => {
+                    state.draft = Some(Annotation::Rect {
+                        rect: Rect {
+                            x1: point.x,
+                            y1: point.y,
+                            x2: point.x,
+                            y2: point.y,
+                        },
                         color: state.color,
                         width: state.stroke_width,
+                        fill: state.fill(),
                     });
                 }
-                Tool::Rect => {
-                    state.draft = Some(Annotation::Rect {
+                Tool::Ellipse => {
+                    state.draft = Some(Annotation::Ellipse {
                         rect: Rect {
                             x1: point.x,
                             y1: point.y,
@@ -604,6 +1257,7 @@ pub fn build_ui(app: &adw::Application) {
                         },
                         color: state.color,
                         width: state.stroke_width,
+                        fill: state.fill(),
                     });
                 }
                 Tool::Line | Tool::Arrow => {
@@ -616,17 +1270,26 @@ pub fn build_ui(app: &adw::Application) {
                     });
                 }
                 Tool::Blur => {
-                    state.draft = Some(Annotation::Blur {
-                        rect: Rect {
-                            x1: point.x,
-                            y1: point.y,
-                            x2: point.x,
-                            y2: point.y,
-                        },
-                        pixel_size: 10,
+                    state.draft = Some(state.blur_annotation(point));
+                }
+                Tool::Magnify => {
+                    state.draft = Some(state.magnify_annotation(point));
+                }
+                Tool::Spotlight => {
+                    state.draft = None;
+                    state.begin_spotlight(point);
+                }
+                Tool::Callout if state.text_edit.is_none() => {
+                    state.draft = Some(Annotation::Callout {
+                        pos: point,
+                        tip: point,
+                        text: String::new(),
+                        color: state.color,
+                        size: state.text_size,
+                        font: state.text_font.clone(),
                     });
                 }
-                Tool::Text => {
+                Tool::Text | Tool::Step | Tool::Callout | Tool::Eyedropper => {
                     state.draft = None;
                 }
             }
@@ -636,9 +1299,14 @@ pub fn build_ui(app: &adw::Application) {
     {
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        drag.connect_drag_update(move |_, offset_x, offset_y| {
+        drag.connect_drag_update(move |gesture, offset_x, offset_y| {
+            let straight = gesture
+                .current_event_state()
+                .contains(gdk::ModifierType::SHIFT_MASK);
             let mut state = state.borrow_mut();
-            let Some(start) = state.drag_start_view else { return; };
+            let Some(start) = state.drag_start_view else {
+                return;
+            };
             let current_view = Point {
                 x: start.x + offset_x,
                 y: start.y + offset_y,
@@ -646,15 +1314,19 @@ pub fn build_ui(app: &adw::Application) {
             let current = editor::map_to_image(&state, current_view.x, current_view.y);
             match state.tool {
                 Tool::Select => {
-                    if let Some(index) = state.selected {
-                        if let Some(original) = state.selected_original.as_ref() {
+                    if let (Some(index), Some(original)) =
+                        (state.selected, state.selected_original.as_ref())
+                    {
+                        let mut changed = original.clone();
+                        if let Some(handle) = state.drag_handle {
+                            editor::reshape_annotation(&mut changed, handle, current);
+                        } else {
                             let start_img = editor::map_to_image(&state, start.x, start.y);
                             let dx = current.x - start_img.x;
                             let dy = current.y - start_img.y;
-                            let mut moved = original.clone();
-                            editor::move_annotation(&mut moved, dx, dy);
-                            state.annotations[index] = moved;
+                            editor::drag_annotation(&mut changed, dx, dy);
                         }
+                        state.annotations[index] = changed;
                     }
                 }
                 Tool::Crop => {
@@ -663,20 +1335,28 @@ pub fn build_ui(app: &adw::Application) {
                         rect.y2 = current.y;
                     }
                 }
+                Tool::Spotlight => state.extend_spotlight(current),
                 _ => match state.draft.as_mut() {
                     Some(Annotation::Pen { points, .. }) => {
-                        points.push(current);
+                        editor::extend_stroke(points, current, straight);
                     }
-                    Some(Annotation::Rect { rect, .. }) => {
+                    Some(Annotation::Rect { rect, .. })
+                    | Some(Annotation::Ellipse { rect, .. }) => {
                         rect.x2 = current.x;
                         rect.y2 = current.y;
                     }
                     Some(Annotation::Line { end, .. }) => {
                         *end = current;
                     }
-                    Some(Annotation::Blur { rect, .. }) => {
-                        rect.x2 = current.x;
-                        rect.y2 = current.y;
+                    Some(Annotation::Callout { pos, .. }) => {
+                        *pos = current;
+                    }
+                    Some(blur @ Annotation::Blur { .. }) => {
+                        editor::extend_blur(blur, current);
+                    }
+                    Some(Annotation::Magnify { source, .. }) => {
+                        source.x2 = current.x;
+                        source.y2 = current.y;
                     }
                     _ => {}
                 },
@@ -691,9 +1371,14 @@ pub fn build_ui(app: &adw::Application) {
         let fit_updating = fit_updating.clone();
         let zoom_adjustment = zoom_adjustment.clone();
         let zoom_updating = zoom_updating.clone();
-        drag.connect_drag_end(move |_, offset_x, offset_y| {
+        let text_editor = text_editor.clone();
+        drag.connect_drag_end(move |gesture, offset_x, offset_y| {
+            let straight = gesture
+                .current_event_state()
+                .contains(gdk::ModifierType::SHIFT_MASK);
             let mut did_crop = false;
             let mut new_size = None;
+            let mut new_callout = None;
             {
                 let mut state = state.borrow_mut();
                 if let Some(start) = state.drag_start_view.take() {
@@ -704,44 +1389,102 @@ pub fn build_ui(app: &adw::Application) {
                     let end = editor::map_to_image(&state, end_view.x, end_view.y);
                     match state.tool {
                         Tool::Select => {
-                            state.selected_original = None;
+                            let handle = state.drag_handle.take();
+                            let duplicated = std::mem::take(&mut state.drag_duplicate);
+                            if let (Some(index), Some(original)) =
+                                (state.selected, state.selected_original.take())
+                            {
+                                let start_img = editor::map_to_image(&state, start.x, start.y);
+                                let dx = end.x - start_img.x;
+                                let dy = end.y - start_img.y;
+                                if duplicated {
+                                    let annotation = state.annotations[index].clone();
+                                    state.record(Command::Add { index, annotation });
+                                } else if dx != 0.0 || dy != 0.0 {
+                                    if handle.is_some()
+                                        || matches!(
+                                            original,
+                                            Annotation::Callout { .. } | Annotation::Magnify { .. }
+                                        )
+                                    {
+                                        let after = state.annotations[index].clone();
+                                        state.record(Command::Modify {
+                                            index,
+                                            before: original,
+                                            after,
+                                        });
+                                    } else {
+                                        state.record(Command::Move { index, dx, dy });
+                                    }
+                                }
+                            }
                         }
+                        Tool::Spotlight => state.finish_spotlight(end),
                         Tool::Crop => {
                             if let Some(rect) = state.crop_rect {
                                 if editor::apply_crop(&mut state, rect) {
                                     state.fit_to_window = true;
                                     state.zoom = 1.0;
                                     did_crop = true;
-                                    new_size = state
-                                        .background
-                                        .as_ref()
-                                        .map(|p| (p.width(), p.height()));
+                                    new_size =
+                                        state.background.as_ref().map(|p| (p.width(), p.height()));
                                 }
                             }
                         }
                         _ => {
+                            if let Some(Annotation::Callout { tip, .. }) = state.draft.as_ref() {
+                                let tip = *tip;
+                                let (scale, _, _) = editor::view_transform(&state);
+                                let dragged = (end.x - tip.x).hypot(end.y - tip.y) * scale >= 4.0;
+                                let pos = if dragged {
+                                    end
+                                } else {
+                                    Point {
+                                        x: tip.x + editor::CALLOUT_OFFSET,
+                                        y: tip.y - editor::CALLOUT_OFFSET,
+                                    }
+                                };
+                                state.draft = None;
+                                new_callout = Some(TextEdit {
+                                    index: None,
+                                    pos,
+                                    tip: Some(tip),
+                                });
+                            }
                             if let Some(mut draft) = state.draft.take() {
                                 match &mut draft {
                                     Annotation::Line { end: line_end, .. } => *line_end = end,
-                                    Annotation::Rect { rect, .. } => {
+                                    Annotation::Rect { rect, .. }
+                                    | Annotation::Ellipse { rect, .. } => {
                                         rect.x2 = end.x;
                                         rect.y2 = end.y;
                                     }
-                                    Annotation::Blur { rect, .. } => {
-                                        rect.x2 = end.x;
-                                        rect.y2 = end.y;
+                                    blur @ Annotation::Blur { .. } => {
+                                        editor::extend_blur(blur, end);
                                     }
                                     Annotation::Pen { points, .. } => {
-                                        points.push(end);
+                                        editor::extend_stroke(points, end, straight);
+                                    }
+                                    Annotation::Magnify { source, .. } => {
+                                        source.x2 = end.x;
+                                        source.y2 = end.y;
                                     }
                                     _ => {}
                                 }
-                                state.push_annotation(draft);
+                                // A magnifier needs a source region before its inset can be placed.
+                                if !matches!(draft, Annotation::Magnify { .. })
+                                    || state.place_magnifier(&mut draft)
+                                {
+                                    state.push_annotation(draft);
+                                }
                             }
                         }
                     }
                 }
             }
+            if let Some(edit) = new_callout {
+                text_editor.begin(edit);
+            }
             if did_crop {
                 if let Some((width, height)) = new_size {
                     drawing_area.set_content_width(width);
@@ -759,30 +1502,78 @@ pub fn build_ui(app: &adw::Application) {
     }
     drawing_area.add_controller(drag);
 
+    {
+        let state = state.clone();
+        let eyedropper_color = eyedropper_color.clone();
+        let eyedropper_preview = eyedropper_preview.clone();
+        let eyedropper_label = eyedropper_label.clone();
+        let motion = gtk::EventControllerMotion::new();
+        motion.connect_motion(move |_, x, y| {
+            let state = state.borrow();
+            if state.tool != Tool::Eyedropper {
+                return;
+            }
+            let color = editor::sample_color(&state, editor::map_to_image(&state, x, y));
+            eyedropper_color.set(color);
+            eyedropper_label.set_text(
+                &color
+                    .map(|color| editor::hex_color(&color))
+                    .unwrap_or_default(),
+            );
+            eyedropper_preview.queue_draw();
+        });
+        drawing_area.add_controller(motion);
+    }
+
     let click = gtk::GestureClick::new();
     {
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        click.connect_pressed(move |_, _, x, y| {
-            let pos = {
+        let text_editor = text_editor.clone();
+        let set_status = set_status.clone();
+        let remember_color = remember_color.clone();
+        click.connect_pressed(move |_, n_press, x, y| {
+            if text_editor.is_active() {
+                text_editor.commit();
+                return;
+            }
+            let (tool, pos, hit_text) = {
                 let state = state.borrow();
-                editor::map_to_image(&state, x, y)
+                let pos = editor::map_to_image(&state, x, y);
+                let hit_text = editor::hit_test(&state.annotations, pos).filter(|index| {
+                    matches!(
+                        state.annotations.get(*index),
+                        Some(Annotation::Text { .. } | Annotation::Callout { .. })
+                    )
+                });
+                (state.tool, pos, hit_text)
             };
-            let mut state = state.borrow_mut();
-            match state.tool {
-                Tool::Text => {
-                    let color = state.color;
-                    let size = state.text_size;
-                    state.push_annotation(Annotation::Text {
-                        pos,
-                        text: "Text".to_string(),
-                        color,
-                        size,
-                    });
+            let edit = TextEdit {
+                index: hit_text,
+                pos,
+                tip: None,
+            };
+            match tool {
+                Tool::Text => text_editor.begin(edit),
+                Tool::Step => {
+                    state.borrow_mut().add_step(pos);
                     drawing_area.queue_draw();
                 }
+                Tool::Eyedropper => {
+                    let picked = editor::sample_color(&state.borrow(), pos);
+                    if let Some(color) = picked {
+                        remember_color(color);
+                        set_status(&format!("Picked {}.", editor::hex_color(&color)));
+                    }
+                }
+                Tool::Select if n_press == 2 && hit_text.is_some() => {
+                    text_editor.begin(edit);
+                }
                 Tool::Select => {
-                    state.selected = editor::hit_test(&state.annotations, pos);
+                    let mut state = state.borrow_mut();
+                    if editor::handle_at(&state, pos).is_none() {
+                        state.selected = editor::hit_test(&state.annotations, pos);
+                    }
                     state.selected_original = state
                         .selected
                         .and_then(|index| state.annotations.get(index).cloned());
@@ -795,22 +1586,96 @@ pub fn build_ui(app: &adw::Application) {
     drawing_area.add_controller(click);
 
     {
-        let buttons = Rc::new(tool_buttons);
+        let add_selection_action = |name: &str, apply: fn(&mut EditorState) -> bool| {
+            let state = state.clone();
+            let drawing_area = drawing_area.clone();
+            let action = gio::SimpleAction::new(name, None);
+            action.connect_activate(move |_, _| {
+                if apply(&mut state.borrow_mut()) {
+                    drawing_area.queue_draw();
+                }
+            });
+            window.add_action(&action);
+        };
+        add_selection_action("delete", EditorState::delete_selected);
+        add_selection_action("duplicate", |state| {
+            state.duplicate_selected(editor::DUPLICATE_OFFSET, editor::DUPLICATE_OFFSET)
+        });
+        add_selection_action("bring-forward", |state| {
+            state.reorder_selected(ZOrder::Forward)
+        });
+        add_selection_action("send-backward", |state| {
+            state.reorder_selected(ZOrder::Backward)
+        });
+        add_selection_action("bring-to-front", |state| {
+            state.reorder_selected(ZOrder::Front)
+        });
+        add_selection_action("send-to-back", |state| state.reorder_selected(ZOrder::Back));
+        add_selection_action(
+            "blur-include-below",
+            EditorState::toggle_selected_blur_below,
+        );
+
+        let context_popover = gtk::PopoverMenu::from_model(Some(&context_menu));
+        context_popover.set_parent(&drawing_area);
+        context_popover.set_has_arrow(false);
+        context_popover.set_halign(gtk::Align::Start);
+        {
+            let context_popover = context_popover.clone();
+            drawing_area.connect_destroy(move |_| context_popover.unparent());
+        }
+
+        let state = state.clone();
+        let drawing_area_for_menu = drawing_area.clone();
+        let secondary = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_SECONDARY)
+            .build();
+        secondary.connect_pressed(move |_, _, x, y| {
+            {
+                let mut state = state.borrow_mut();
+                let pos = editor::map_to_image(&state, x, y);
+                state.selected = editor::hit_test(&state.annotations, pos);
+                state.selected_original = state
+                    .selected
+                    .and_then(|index| state.annotations.get(index).cloned());
+                if state.selected.is_none() {
+                    drawing_area_for_menu.queue_draw();
+                    return;
+                }
+            }
+            drawing_area_for_menu.queue_draw();
+            context_popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+            context_popover.popup();
+        });
+        drawing_area.add_controller(secondary);
+    }
+
+    {
+        let buttons = tool_buttons.clone();
         let state = state.clone();
 
         for (tool, button) in buttons.iter() {
             let tool = *tool;
             let buttons = buttons.clone();
             let state = state.clone();
+            let drawing_area = drawing_area.clone();
+            let eyedropper_preview = eyedropper_preview.clone();
+            let eyedropper_label = eyedropper_label.clone();
+            let update_preferences = update_preferences.clone();
             button.connect_toggled(move |active_button| {
                 if !active_button.is_active() {
                     return;
                 }
+                update_preferences(&|preferences| preferences.tool = tool);
                 for (_, other) in buttons.iter() {
                     if other != active_button {
                         other.set_active(false);
                     }
                 }
+                let eyedropper = tool == Tool::Eyedropper;
+                eyedropper_preview.set_visible(eyedropper);
+                eyedropper_label.set_visible(eyedropper);
+                drawing_area.set_cursor_from_name(eyedropper.then_some("crosshair"));
                 let mut state = state.borrow_mut();
                 state.tool = tool;
                 state.draft = None;
@@ -818,10 +1683,15 @@ pub fn build_ui(app: &adw::Application) {
                 state.selected = None;
                 state.selected_original = None;
             });
+
+            let action = gio::SimpleAction::new(shortcuts::tool_action(tool), None);
+            let button = button.clone();
+            action.connect_activate(move |_, _| button.set_active(true));
+            window.add_action(&action);
         }
 
         for (tool, button) in buttons.iter() {
-            if *tool == Tool::Pen {
+            if *tool == initial.tool {
                 button.set_active(true);
                 break;
             }
@@ -829,71 +1699,34 @@ pub fn build_ui(app: &adw::Application) {
     }
 
     {
-        let colors: Vec<(&str, gdk::RGBA)> = vec![
-            ("color-black", gdk::RGBA::new(0.11, 0.11, 0.11, 1.0)),
-            ("color-white", gdk::RGBA::new(0.96, 0.96, 0.96, 1.0)),
-            ("color-red", gdk::RGBA::new(1.0, 0.30, 0.30, 1.0)),
-            ("color-orange", gdk::RGBA::new(1.0, 0.62, 0.10, 1.0)),
-            ("color-yellow", gdk::RGBA::new(1.0, 0.85, 0.24, 1.0)),
-            ("color-green", gdk::RGBA::new(0.24, 0.86, 0.52, 1.0)),
-            ("color-blue", gdk::RGBA::new(0.23, 0.51, 0.96, 1.0)),
-            ("color-purple", gdk::RGBA::new(0.55, 0.36, 0.96, 1.0)),
-        ];
-
-        let buttons: Vec<(gdk::RGBA, gtk::ToggleButton)> = colors
+        color_palette.append(&swatch_box);
+        color_palette.append(&recent_separator);
+        color_palette.append(&recent_box);
+        color_palette.append(&color_button);
+        if let Some((_, button)) = tool_buttons
             .iter()
-            .map(|(class, color)| {
-                let button = gtk::ToggleButton::builder().build();
-                button.add_css_class("color-swatch");
-                button.add_css_class(class);
-                button.set_tooltip_text(Some(&class["color-".len()..]));
-                (*color, button)
-            })
-            .collect();
-
-        for (_, button) in buttons.iter() {
+            .find(|(tool, _)| *tool == Tool::Eyedropper)
+        {
             color_palette.append(button);
         }
-        color_palette.append(&color_button);
-
-        let buttons = Rc::new(buttons);
-        let state = state.clone();
-
-        for (color, button) in buttons.iter() {
-            let color = *color;
-            let buttons = buttons.clone();
-            let state = state.clone();
-            button.connect_toggled(move |active_button| {
-                if !active_button.is_active() {
-                    return;
-                }
-                for (_, other) in buttons.iter() {
-                    if other != active_button {
-                        other.set_active(false);
-                    }
-                }
-                state.borrow_mut().color = color;
-            });
-        }
-
-        for (color, button) in buttons.iter() {
-            if (color.red() - 1.0).abs() < 0.001 && (color.green() - 0.30).abs() < 0.001 {
-                button.set_active(true);
-                break;
-            }
-        }
+        color_palette.append(&screen_color_button);
+        color_palette.append(&eyedropper_preview);
+        color_palette.append(&eyedropper_label);
+        color_palette.append(&palette_button);
+        refresh_palettes();
     }
     {
         let state = state.clone();
         let window = window.clone();
         let dialog = color_dialog.clone();
+        let remember_color = remember_color.clone();
         color_button.connect_clicked(move |_| {
             let current = state.borrow().color;
             dialog.choose_rgba(Some(&window), Some(&current), None::<&gio::Cancellable>, {
-                let state = state.clone();
+                let remember_color = remember_color.clone();
                 move |result| {
                     if let Ok(color) = result {
-                        state.borrow_mut().color = color;
+                        remember_color(color);
                     }
                 }
             });
@@ -901,83 +1734,289 @@ pub fn build_ui(app: &adw::Application) {
     }
     {
         let state = state.clone();
+        let update_preferences = update_preferences.clone();
         size_spin.connect_value_changed(move |spin| {
-            state.borrow_mut().stroke_width = spin.value();
+            let width = spin.value();
+            state.borrow_mut().stroke_width = width;
+            update_preferences(&|preferences| preferences.stroke_width = width);
+        });
+    }
+    {
+        let update_preferences = update_preferences.clone();
+        delay_spin.connect_value_changed(move |spin| {
+            let delay = spin.value();
+            update_preferences(&|preferences| preferences.capture_delay = delay);
+        });
+    }
+    {
+        let update_preferences = update_preferences.clone();
+        interactive_toggle.connect_active_notify(move |switch| {
+            let interactive = switch.is_active();
+            update_preferences(&|preferences| preferences.interactive = interactive);
+        });
+    }
+    let update_fill: Rc<dyn Fn()> = {
+        let state = state.clone();
+        let fill_switch = fill_switch.clone();
+        let fill_color_button = fill_color_button.clone();
+        let fill_opacity_adjustment = fill_opacity_adjustment.clone();
+        Rc::new(move || {
+            let color = fill_color_button.rgba();
+            let mut state = state.borrow_mut();
+            state.fill_enabled = fill_switch.is_active();
+            state.fill_color = gdk::RGBA::new(
+                color.red(),
+                color.green(),
+                color.blue(),
+                fill_opacity_adjustment.value() as f32,
+            );
+        })
+    };
+    {
+        let update_fill = update_fill.clone();
+        fill_switch.connect_active_notify(move |_| update_fill());
+    }
+    {
+        let update_fill = update_fill.clone();
+        fill_color_button.connect_rgba_notify(move |_| update_fill());
+    }
+    fill_opacity_adjustment.connect_value_changed(move |_| update_fill());
+    let update_secure_note = {
+        let state = state.clone();
+        let blur_secure_note = blur_secure_note.clone();
+        Rc::new(move || {
+            let state = state.borrow();
+            let coarser = match state.blur_mode {
+                BlurMode::Solid => false,
+                BlurMode::Gaussian => true,
+                BlurMode::Pixelate => state.blur_strength < redact::MIN_BLOCK_SIZE,
+            };
+            blur_secure_note.set_visible(state.secure_export && coarser);
+        })
+    };
+    update_secure_note();
+    {
+        let state = state.clone();
+        let blur_strength_spin = blur_strength_spin.clone();
+        let update_secure_note = update_secure_note.clone();
+        blur_mode_dropdown.connect_selected_notify(move |dropdown| {
+            let mode = match dropdown.selected() {
+                1 => BlurMode::Gaussian,
+                2 => BlurMode::Solid,
+                _ => BlurMode::Pixelate,
+            };
+            blur_strength_spin.set_sensitive(mode != BlurMode::Solid);
+            state.borrow_mut().blur_mode = mode;
+            update_secure_note();
+        });
+    }
+    {
+        let state = state.clone();
+        blur_below_switch.connect_active_notify(move |switch| {
+            state.borrow_mut().blur_include_below = switch.is_active();
+        });
+    }
+    {
+        let state = state.clone();
+        magnify_connectors_switch.connect_active_notify(move |switch| {
+            state.borrow_mut().magnify_connectors = switch.is_active();
+        });
+    }
+    {
+        let state = state.clone();
+        spotlight_shape_dropdown.connect_selected_notify(move |dropdown| {
+            state.borrow_mut().spotlight_shape = match dropdown.selected() {
+                1 => SpotlightShape::Ellipse,
+                _ => SpotlightShape::Rect,
+            };
+        });
+    }
+    {
+        let state = state.clone();
+        spotlight_opacity_adjustment.connect_value_changed(move |adjustment| {
+            state.borrow_mut().spotlight_opacity = adjustment.value();
+        });
+    }
+    {
+        let state = state.clone();
+        let update_preferences = update_preferences.clone();
+        let update_secure_note = update_secure_note.clone();
+        secure_switch.connect_active_notify(move |switch| {
+            let secure = switch.is_active();
+            state.borrow_mut().secure_export = secure;
+            update_secure_note();
+            update_preferences(&|preferences| preferences.secure_export = secure);
+        });
+    }
+    {
+        let state = state.clone();
+        blur_shape_dropdown.connect_selected_notify(move |dropdown| {
+            state.borrow_mut().blur_shape = match dropdown.selected() {
+                1 => BlurShape::Brush,
+                2 => BlurShape::Lasso,
+                _ => BlurShape::Rect,
+            };
+        });
+    }
+    {
+        let state = state.clone();
+        blur_strength_spin.connect_value_changed(move |spin| {
+            state.borrow_mut().blur_strength = spin.value() as i32;
+            update_secure_note();
+        });
+    }
+    {
+        let state = state.clone();
+        let update_preferences = update_preferences.clone();
+        text_size_spin.connect_value_changed(move |spin| {
+            let size = spin.value();
+            state.borrow_mut().text_size = size;
+            update_preferences(&|preferences| preferences.text_size = size);
+        });
+    }
+    {
+        let state = state.clone();
+        font_button.connect_font_desc_notify(move |button| {
+            if let Some(desc) = button.font_desc() {
+                state.borrow_mut().text_font = editor::TextFont::from_description(&desc);
+            }
         });
     }
     {
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        undo_button.connect_clicked(move |_| {
+        let undo = gio::SimpleAction::new("undo", None);
+        undo.connect_activate(move |_, _| {
             state.borrow_mut().undo();
             drawing_area.queue_draw();
         });
+        window.add_action(&undo);
     }
     {
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        redo_button.connect_clicked(move |_| {
+        let redo = gio::SimpleAction::new("redo", None);
+        redo.connect_activate(move |_, _| {
             state.borrow_mut().redo();
             drawing_area.queue_draw();
         });
+        window.add_action(&redo);
     }
 
     {
         let state = state.clone();
         let set_status = set_status.clone();
-        copy_button.connect_clicked(move |_| {
-            let state = state.borrow();
-            let Some(pixbuf) = editor::render_to_pixbuf(&state) else {
-                set_status("Nothing to copy yet.");
-                return;
+        let copy = gio::SimpleAction::new("copy", None);
+        copy.connect_activate(move |_, _| {
+            let pixbuf = match editor::export_pixbuf(&state.borrow()) {
+                Ok(pixbuf) => pixbuf,
+                Err(err) => {
+                    let msg = format!("Copy failed: {err}");
+                    set_status(&msg);
+                    return;
+                }
             };
-            let texture = gdk::Texture::for_pixbuf(&pixbuf);
-            if let Some(display) = gdk::Display::default() {
-                display.clipboard().set_texture(&texture);
+            if copy_to_clipboard(&pixbuf) {
                 set_status("Copied to clipboard.");
             } else {
                 set_status("Clipboard unavailable.");
             }
         });
+        window.add_action(&copy);
     }
 
-    {
-        let window = window.clone();
+    let open_file: Rc<dyn Fn(&gio::File)> = {
+        let state = state.clone();
+        let drawing_area = drawing_area.clone();
         let set_status = set_status.clone();
         let apply_background = apply_background.clone();
+        let tool_buttons = tool_buttons.clone();
+        let size_spin = size_spin.clone();
+        let text_size_spin = text_size_spin.clone();
+        let font_button = font_button.clone();
+        let preferences_paused = preferences_paused.clone();
+        let selected_swatch = selected_swatch.clone();
+        let refresh_palettes = refresh_palettes.clone();
+        Rc::new(move |file: &gio::File| {
+            let Some(path) = file.path() else {
+                set_status("Failed to resolve file path.");
+                return;
+            };
+            let is_project = path
+                .extension()
+                .is_some_and(|ext| ext == project::PROJECT_EXTENSION);
+            if is_project {
+                match project::load_project(&path) {
+                    Ok(project) => {
+                        apply_background(project.background.clone());
+                        // The project's tool and sizes belong to it, not to the saved preferences.
+                        let paused = preferences_paused.replace(true);
+                        for (tool, button) in tool_buttons.iter() {
+                            if *tool == project.tool {
+                                button.set_active(true);
+                            }
+                        }
+                        size_spin.set_value(project.stroke_width);
+                        text_size_spin.set_value(project.text_size);
+                        font_button
+                            .set_font_desc(&project.text_font.description(project.text_size));
+                        project.apply(&mut state.borrow_mut());
+                        selected_swatch.set(None);
+                        refresh_palettes();
+                        preferences_paused.set(paused);
+                        drawing_area.queue_draw();
+                        set_status("Opened project.");
+                    }
+                    Err(err) => {
+                        let msg = format!("Failed to open project: {err}");
+                        set_status(&msg);
+                    }
+                }
+            } else {
+                match gdk_pixbuf::Pixbuf::from_file(&path) {
+                    Ok(pixbuf) => {
+                        apply_background(pixbuf);
+                        set_status("Opened image.");
+                    }
+                    Err(err) => {
+                        let msg = format!("Failed to open image: {err}");
+                        set_status(&msg);
+                    }
+                }
+            }
+        })
+    };
+
+    {
+        let window_for_action = window.clone();
+        let set_status = set_status.clone();
+        let open_file = open_file.clone();
         let file_dialog = gtk::FileDialog::new();
         file_dialog.set_title("Open Image");
-        open_button.connect_clicked(move |_| {
-            let apply_background = apply_background.clone();
+        let open = gio::SimpleAction::new("open", None);
+        open.connect_activate(move |_, _| {
+            let open_file = open_file.clone();
             let set_status = set_status.clone();
-            file_dialog.open(Some(&window), None::<&gio::Cancellable>, move |res| {
-                match res {
-                    Ok(file) => match file.path() {
-                        Some(path) => match gdk_pixbuf::Pixbuf::from_file(path) {
-                            Ok(pixbuf) => {
-                                apply_background(pixbuf);
-                                set_status("Opened image.");
-                            }
-                            Err(err) => {
-                                let msg = format!("Failed to open image: {err}");
-                                set_status(&msg);
-                            }
-                        },
-                        None => set_status("Failed to resolve file path."),
-                    },
+            file_dialog.open(
+                Some(&window_for_action),
+                None::<&gio::Cancellable>,
+                move |res| match res {
+                    Ok(file) => open_file(&file),
                     Err(err) => {
                         let msg = format!("Open canceled: {err}");
                         set_status(&msg);
                     }
-                }
-            });
+                },
+            );
         });
+        window.add_action(&open);
     }
 
     {
         let set_status = set_status.clone();
         let apply_background = apply_background.clone();
-        paste_button.connect_clicked(move |_| {
+        let paste = gio::SimpleAction::new("paste", None);
+        paste.connect_activate(move |_, _| {
             let Some(display) = gdk::Display::default() else {
                 set_status("Clipboard unavailable.");
                 return;
@@ -1004,30 +2043,163 @@ pub fn build_ui(app: &adw::Application) {
                 }
             });
         });
+        window.add_action(&paste);
     }
 
+    // GtkFileDialog doesn't report which filter was picked, so saving images uses the native
+    // chooser to let the selected filter decide the format of a name without an extension.
+    #[allow(deprecated)]
     {
-        let window = window.clone();
         let state = state.clone();
         let set_status = set_status.clone();
+        let preferences = preferences.clone();
+        // Formats chosen in the dialog only carry over to the next save in this session; the
+        // "Default format" preference is left as the user set it.
+        let last_format: Rc<Cell<Option<ImageFormat>>> = Rc::new(Cell::new(None));
+        let pending: Rc<RefCell<Option<(Pixbuf, ImageFormat, export::SaveOptions)>>> =
+            Rc::new(RefCell::new(None));
+        let chooser = gtk::FileChooserNative::new(
+            Some("Save Image"),
+            Some(&window),
+            gtk::FileChooserAction::Save,
+            None,
+            None,
+        );
+        chooser.set_modal(true);
+        for format in ImageFormat::ALL {
+            chooser.add_filter(&image_filter(format));
+        }
+        {
+            let set_status = set_status.clone();
+            let last_format = last_format.clone();
+            let pending = pending.clone();
+            chooser.connect_response(move |chooser, response| {
+                let Some((pixbuf, fallback, options)) = pending.borrow_mut().take() else {
+                    return;
+                };
+                if response != gtk::ResponseType::Accept {
+                    set_status("Save canceled.");
+                    return;
+                }
+                let Some(path) = chooser.file().and_then(|file| file.path()) else {
+                    set_status("Failed to resolve save path.");
+                    return;
+                };
+                // The file name's extension picks the format, then the filter picked in the
+                // dialog, then the last format used.
+                let fallback = chooser
+                    .filter()
+                    .and_then(|filter| filter_format(&filter))
+                    .unwrap_or(fallback);
+                let saved = export::resolve_path(path, fallback).and_then(|(path, format)| {
+                    export::save(&pixbuf, &path, format, &options).map(|()| format)
+                });
+                match saved {
+                    Ok(format) => {
+                        last_format.set(Some(format));
+                        let msg = format!("Saved {}.", format.label());
+                        set_status(&msg);
+                    }
+                    Err(err) => {
+                        let msg = format!("Save failed: {err}");
+                        set_status(&msg);
+                    }
+                }
+            });
+        }
+        let save = gio::SimpleAction::new("save", None);
+        save.connect_activate(move |_, _| {
+            let pixbuf = match editor::export_pixbuf(&state.borrow()) {
+                Ok(pixbuf) => pixbuf,
+                Err(err) => {
+                    let msg = format!("Save failed: {err}");
+                    set_status(&msg);
+                    return;
+                }
+            };
+            let (fallback, options) = {
+                let preferences = preferences.borrow();
+                (preferences.export_format, preferences.save_options())
+            };
+            let default_format = last_format.get().unwrap_or(fallback);
+            if let Some(filter) = chooser
+                .filters()
+                .iter::<gtk::FileFilter>()
+                .flatten()
+                .find(|filter| filter_format(filter) == Some(default_format))
+            {
+                chooser.set_filter(&filter);
+            }
+            chooser.set_current_name(&format!("Screenshot.{}", default_format.extensions()[0]));
+            *pending.borrow_mut() = Some((pixbuf, default_format, options));
+            chooser.show();
+        });
+        window.add_action(&save);
+    }
+
+    {
+        let window_for_action = window.clone();
+        let set_status = set_status.clone();
+        let open_file = open_file.clone();
         let file_dialog = gtk::FileDialog::new();
-        file_dialog.set_title("Save PNG");
-        save_button.connect_clicked(move |_| {
-            let Some(pixbuf) = editor::render_to_pixbuf(&state.borrow()) else {
+        file_dialog.set_title("Open Project");
+        file_dialog.set_default_filter(Some(&project_filter()));
+        let open_project = gio::SimpleAction::new("open-project", None);
+        open_project.connect_activate(move |_, _| {
+            let open_file = open_file.clone();
+            let set_status = set_status.clone();
+            file_dialog.open(
+                Some(&window_for_action),
+                None::<&gio::Cancellable>,
+                move |res| match res {
+                    Ok(file) => open_file(&file),
+                    Err(err) => {
+                        let msg = format!("Open canceled: {err}");
+                        set_status(&msg);
+                    }
+                },
+            );
+        });
+        window.add_action(&open_project);
+    }
+
+    {
+        let window_for_action = window.clone();
+        let state = state.clone();
+        let set_status = set_status.clone();
+        let file_dialog = gtk::FileDialog::new();
+        file_dialog.set_title("Save Project");
+        file_dialog.set_initial_name(Some("screenshot.greatshot"));
+        file_dialog.set_default_filter(Some(&project_filter()));
+        let save_project = gio::SimpleAction::new("save-project", None);
+        save_project.connect_activate(move |_, _| {
+            if state.borrow().background.is_none() {
                 set_status("Nothing to save yet.");
                 return;
-            };
-            let texture = gdk::Texture::for_pixbuf(&pixbuf);
+            }
+            let state = state.clone();
             let set_status = set_status.clone();
-            file_dialog.save(Some(&window), None::<&gio::Cancellable>, move |res| {
+            file_dialog.save(Some(&window_for_action), None::<&gio::Cancellable>, move |res| {
                 match res {
                     Ok(file) => match file.path() {
                         Some(mut path) => {
                             if path.extension().is_none() {
-                                path.set_extension("png");
+                                path.set_extension(project::PROJECT_EXTENSION);
                             }
-                            match texture.save_to_png(&path) {
-                                Ok(()) => set_status("Saved PNG."),
+                            let state = state.borrow();
+                            match project::save_project(&state, &path) {
+                                Ok(())
+                                    if state.secure_export
+                                        && state
+                                            .annotations
+                                            .iter()
+                                            .any(|annotation| matches!(annotation, Annotation::Blur { .. })) =>
+                                {
+                                    set_status(
+                                        "Saved project. Redactions are burned into its background; turn off Secure export to keep them editable.",
+                                    )
+                                }
+                                Ok(()) => set_status("Saved project."),
                                 Err(err) => {
                                     let msg = format!("Save failed: {err}");
                                     set_status(&msg);
@@ -1043,6 +2215,30 @@ pub fn build_ui(app: &adw::Application) {
                 }
             });
         });
+        window.add_action(&save_project);
+    }
+    {
+        let window_for_action = window.clone();
+        let preferences = preferences.clone();
+        let update_preferences = update_preferences.clone();
+        let controls = preferences_window::SharedControls {
+            delay: delay_adjustment.clone(),
+            stroke_width: size_adjustment.clone(),
+            text_size: text_size_adjustment.clone(),
+            interactive: interactive_toggle.clone(),
+            secure_export: secure_switch.clone(),
+        };
+        let show_preferences = gio::SimpleAction::new("preferences", None);
+        show_preferences.connect_activate(move |_, _| {
+            preferences_window::window(
+                &window_for_action,
+                &preferences,
+                &update_preferences,
+                &controls,
+            )
+            .present();
+        });
+        window.add_action(&show_preferences);
     }
 
     {
@@ -1073,12 +2269,15 @@ pub fn build_ui(app: &adw::Application) {
         let zoom_adjustment = zoom_adjustment.clone();
         let zoom_updating = zoom_updating.clone();
         let fit_updating = fit_updating.clone();
+        let update_preferences = update_preferences.clone();
         fit_toggle.connect_toggled(move |toggle| {
             if fit_updating.get() {
                 return;
             }
+            let fit = toggle.is_active();
+            update_preferences(&|preferences| preferences.fit_to_window = fit);
             let mut state = state.borrow_mut();
-            state.fit_to_window = toggle.is_active();
+            state.fit_to_window = fit;
             if state.fit_to_window {
                 let (scale, _, _) = editor::view_transform(&state);
                 zoom_updating.set(true);
@@ -1133,4 +2332,116 @@ pub fn build_ui(app: &adw::Application) {
         });
         drawing_area.add_controller(scroll);
     }
+
+    {
+        let fit_toggle = fit_toggle.clone();
+        let zoom_fit = gio::SimpleAction::new("zoom-fit", None);
+        zoom_fit.connect_activate(move |_, _| fit_toggle.set_active(true));
+        window.add_action(&zoom_fit);
+
+        let zoom_reset = zoom_reset.clone();
+        let actual_size = gio::SimpleAction::new("zoom-reset", None);
+        actual_size.connect_activate(move |_, _| zoom_reset.emit_clicked());
+        window.add_action(&actual_size);
+    }
+    {
+        let window_for_action = window.clone();
+        let preferences = preferences.clone();
+        let show_shortcuts = gio::SimpleAction::new("shortcuts", None);
+        show_shortcuts.connect_activate(move |_, _| {
+            shortcuts::window(&window_for_action, &preferences.borrow().shortcuts).present();
+        });
+        window.add_action(&show_shortcuts);
+    }
+    // Bound on the window rather than as application accelerators so that focused text
+    // fields get keys like "t" or Ctrl+C before the single-key tool shortcuts do.
+    let apply_shortcuts = {
+        let window = window.clone();
+        let controller = RefCell::new(None::<gtk::ShortcutController>);
+        move |overrides: &BTreeMap<String, Vec<String>>| {
+            if let Some(previous) = controller.borrow_mut().take() {
+                window.remove_controller(&previous);
+            }
+            let current = shortcuts::controller(overrides);
+            window.add_controller(current.clone());
+            controller.replace(Some(current));
+            fill_menu(&main_menu, MAIN_MENU, overrides);
+            fill_menu(&context_menu, CONTEXT_MENU, overrides);
+        }
+    };
+    apply_shortcuts(&initial.shortcuts);
+    let unknown = shortcuts::unknown_actions(&initial.shortcuts);
+    if !unknown.is_empty() {
+        let msg = format!(
+            "Unknown shortcut actions in settings: {}",
+            unknown.join(", ")
+        );
+        set_status(&msg);
+    }
+    // Shortcuts are remapped by editing settings.toml, so pick up changes while running.
+    if let Ok(monitor) = gio::File::for_path(preferences::path()).monitor_file(
+        gio::FileMonitorFlags::WATCH_MOVES,
+        None::<&gio::Cancellable>,
+    ) {
+        let preferences = preferences.clone();
+        let set_status = set_status.clone();
+        monitor.connect_changed(move |_, _, _, event| {
+            if !matches!(
+                event,
+                gio::FileMonitorEvent::ChangesDoneHint
+                    | gio::FileMonitorEvent::MovedIn
+                    | gio::FileMonitorEvent::Renamed
+            ) {
+                return;
+            }
+            let overrides = match preferences::load() {
+                Ok(loaded) => loaded.shortcuts,
+                Err(err) => {
+                    let msg = format!("{err}. Keyboard shortcuts weren't reloaded.");
+                    set_status(&msg);
+                    return;
+                }
+            };
+            if preferences.borrow().shortcuts == overrides {
+                return;
+            }
+            apply_shortcuts(&overrides);
+            let unknown = shortcuts::unknown_actions(&overrides);
+            let msg = if unknown.is_empty() {
+                "Reloaded keyboard shortcuts.".to_string()
+            } else {
+                format!(
+                    "Reloaded keyboard shortcuts. Unknown actions: {}",
+                    unknown.join(", ")
+                )
+            };
+            set_status(&msg);
+            preferences.borrow_mut().shortcuts = overrides;
+        });
+        window.connect_destroy(move |_| {
+            monitor.cancel();
+        });
+    }
+    let load_errors: Vec<String> = [
+        preferences_error.map(|err| format!("{err}. Settings won't be saved until it's fixed.")),
+        palette_error.map(|err| format!("{err}. Palettes won't be saved until it's fixed.")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !load_errors.is_empty() {
+        set_status(&load_errors.join(" "));
+    }
+
+    preferences_paused.set(false);
+
+    EditorWindow {
+        window,
+        open_file,
+        capture: start_capture,
+        delay_spin,
+        interactive_toggle,
+        preferences_paused,
+        capture_failed,
+    }
 }
@@ -0,0 +1,452 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use adw::prelude::*;
+use gtk::gdk;
+use gtk::gio;
+
+use super::{ICON_SWATCHES, IconImages, UpdatePreferences, create_icon};
+use crate::editor::{self, EditorState};
+use crate::palette::{self, PaletteStore, Swatch};
+
+pub struct Palettes {
+    pub button: gtk::MenuButton,
+    pub swatch_box: gtk::Box,
+    pub recent_box: gtk::Box,
+    pub recent_separator: gtk::Separator,
+    pub selected_swatch: Rc<Cell<Option<usize>>>,
+    pub refresh: Rc<dyn Fn()>,
+    pub remember_color: Rc<dyn Fn(gdk::RGBA)>,
+    pub error: Option<String>,
+}
+
+// Builds the swatch strip and the palette popover, which loads, edits, imports and exports palettes.
+pub fn build(
+    window: &adw::ApplicationWindow,
+    state: &Rc<RefCell<EditorState>>,
+    set_status: Rc<dyn Fn(&str)>,
+    update_preferences: &UpdatePreferences,
+    icon_images: &IconImages,
+    icon_color: &Rc<RefCell<String>>,
+) -> Palettes {
+    let (loaded_palettes, palette_error) = match palette::load() {
+        Ok(store) => (store, None),
+        Err(err) => (PaletteStore::default(), Some(err)),
+    };
+    let palettes_writable = palette_error.is_none();
+    let palette_store: Rc<RefCell<PaletteStore>> = Rc::new(RefCell::new(loaded_palettes));
+    let selected_swatch: Rc<Cell<Option<usize>>> = Rc::new(Cell::new(None));
+    let swatch_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    let recent_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    let recent_separator = gtk::Separator::new(gtk::Orientation::Vertical);
+    let palette_list = gtk::StringList::new(&[]);
+    let palette_dropdown = gtk::DropDown::builder()
+        .model(&palette_list)
+        .tooltip_text("Active palette")
+        .build();
+    let palette_updating = Rc::new(Cell::new(false));
+    // Swatch colors come from user palettes, so their backgrounds are generated CSS classes.
+    let swatch_css = gtk::CssProvider::new();
+    if let Some(display) = gdk::Display::default() {
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &swatch_css,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+    }
+    let persist_palettes: Rc<dyn Fn()> = {
+        let palette_store = palette_store.clone();
+        let set_status = set_status.clone();
+        Rc::new(move || {
+            if !palettes_writable {
+                return;
+            }
+            if let Err(err) = palette::save(&palette_store.borrow()) {
+                let msg = format!("Failed to save palettes: {err}");
+                set_status(&msg);
+            }
+        })
+    };
+    let refresh_palettes: Rc<dyn Fn()> = {
+        let state = state.clone();
+        let palette_store = palette_store.clone();
+        let selected_swatch = selected_swatch.clone();
+        let swatch_box = swatch_box.clone();
+        let recent_box = recent_box.clone();
+        let recent_separator = recent_separator.clone();
+        let palette_list = palette_list.clone();
+        let palette_dropdown = palette_dropdown.clone();
+        let palette_updating = palette_updating.clone();
+        let update_preferences = update_preferences.clone();
+        Rc::new(move || {
+            while let Some(child) = swatch_box.first_child() {
+                swatch_box.remove(&child);
+            }
+            while let Some(child) = recent_box.first_child() {
+                recent_box.remove(&child);
+            }
+            let (names, active, swatches, recent) = {
+                let store = palette_store.borrow();
+                let names: Vec<String> = store.palettes.iter().map(|p| p.name.clone()).collect();
+                let swatches = store
+                    .active_palette()
+                    .map(|palette| palette.swatches.clone())
+                    .unwrap_or_default();
+                (names, store.active, swatches, store.recent.clone())
+            };
+            palette_updating.set(true);
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            palette_list.splice(0, palette_list.n_items(), &names);
+            palette_dropdown.set_selected(active as u32);
+            palette_updating.set(false);
+
+            let mut css = String::new();
+            let buttons: Vec<gtk::ToggleButton> = swatches
+                .iter()
+                .enumerate()
+                .map(|(index, swatch)| {
+                    let class = format!("palette-swatch-{index}");
+                    css.push_str(&format!(
+                        ".{class} {{ background: {}; }}\n",
+                        swatch.color.to_str()
+                    ));
+                    let button = gtk::ToggleButton::builder().build();
+                    button.add_css_class("color-swatch");
+                    button.add_css_class(&class);
+                    let hex = editor::hex_color(&swatch.color);
+                    let tooltip = if swatch.name.is_empty() {
+                        hex
+                    } else {
+                        format!("{} ({hex})", swatch.name)
+                    };
+                    button.set_tooltip_text(Some(&tooltip));
+                    swatch_box.append(&button);
+                    button
+                })
+                .collect();
+            let buttons = Rc::new(buttons);
+            for (index, color) in recent.iter().enumerate() {
+                let class = format!("recent-swatch-{index}");
+                css.push_str(&format!(".{class} {{ background: {}; }}\n", color.to_str()));
+                let button = gtk::Button::builder().build();
+                button.add_css_class("color-swatch");
+                button.add_css_class(&class);
+                button.set_tooltip_text(Some(&format!("Recent: {}", editor::hex_color(color))));
+                recent_box.append(&button);
+                let color = *color;
+                let state = state.clone();
+                let buttons = buttons.clone();
+                let selected_swatch = selected_swatch.clone();
+                let update_preferences = update_preferences.clone();
+                button.connect_clicked(move |_| {
+                    for other in buttons.iter() {
+                        other.set_active(false);
+                    }
+                    selected_swatch.set(None);
+                    state.borrow_mut().color = color;
+                    update_preferences(&|preferences| preferences.color = color);
+                });
+            }
+            swatch_css.load_from_string(&css);
+            recent_separator.set_visible(!recent.is_empty());
+
+            for (index, button) in buttons.iter().enumerate() {
+                let color = swatches[index].color;
+                let state = state.clone();
+                let buttons = buttons.clone();
+                let selected_swatch = selected_swatch.clone();
+                let update_preferences = update_preferences.clone();
+                button.connect_toggled(move |active_button| {
+                    if !active_button.is_active() {
+                        return;
+                    }
+                    for other in buttons.iter() {
+                        if other != active_button {
+                            other.set_active(false);
+                        }
+                    }
+                    selected_swatch.set(Some(index));
+                    state.borrow_mut().color = color;
+                    update_preferences(&|preferences| preferences.color = color);
+                });
+            }
+
+            let current = state.borrow().color;
+            let selected = selected_swatch
+                .get()
+                .filter(|index| *index < swatches.len())
+                .or_else(|| {
+                    swatches
+                        .iter()
+                        .position(|swatch| palette::same_color(&swatch.color, &current))
+                });
+            selected_swatch.set(None);
+            if let Some(button) = selected.and_then(|index| buttons.get(index)) {
+                button.set_active(true);
+            }
+        })
+    };
+    // Colors picked outside the palette (dialog, eyedropper, screen) are kept as recent colors.
+    let remember_color: Rc<dyn Fn(gdk::RGBA)> = {
+        let state = state.clone();
+        let palette_store = palette_store.clone();
+        let selected_swatch = selected_swatch.clone();
+        let persist_palettes = persist_palettes.clone();
+        let refresh_palettes = refresh_palettes.clone();
+        let update_preferences = update_preferences.clone();
+        Rc::new(move |color| {
+            state.borrow_mut().color = color;
+            update_preferences(&|preferences| preferences.color = color);
+            palette_store.borrow_mut().push_recent(color);
+            persist_palettes();
+            selected_swatch.set(None);
+            refresh_palettes();
+        })
+    };
+    let palette_button = gtk::MenuButton::builder()
+        .child(&create_icon(ICON_SWATCHES, icon_images, icon_color))
+        .tooltip_text("Palettes")
+        .build();
+    palette_button.add_css_class("color-custom");
+    let add_swatch_button = gtk::Button::with_label("Add Current");
+    let remove_swatch_button = gtk::Button::with_label("Remove");
+    let move_left_button = gtk::Button::with_label("Move Left");
+    let move_right_button = gtk::Button::with_label("Move Right");
+    let new_palette_button = gtk::Button::with_label("New");
+    let delete_palette_button = gtk::Button::with_label("Delete");
+    let import_palette_button = gtk::Button::with_label("Import…");
+    let export_palette_button = gtk::Button::with_label("Export…");
+    let palette_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(8)
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .build();
+    let palette_row = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let palette_label = gtk::Label::new(Some("Palette"));
+    palette_label.set_xalign(0.0);
+    palette_label.set_hexpand(true);
+    palette_row.append(&palette_label);
+    palette_row.append(&palette_dropdown);
+    let swatch_actions = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    swatch_actions.append(&add_swatch_button);
+    swatch_actions.append(&remove_swatch_button);
+    swatch_actions.append(&move_left_button);
+    swatch_actions.append(&move_right_button);
+    let palette_actions = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    palette_actions.append(&new_palette_button);
+    palette_actions.append(&delete_palette_button);
+    palette_actions.append(&import_palette_button);
+    palette_actions.append(&export_palette_button);
+    palette_box.append(&palette_row);
+    palette_box.append(&swatch_actions);
+    palette_box.append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+    palette_box.append(&palette_actions);
+    let palette_popover = gtk::Popover::new();
+    palette_popover.set_child(Some(&palette_box));
+    palette_button.set_popover(Some(&palette_popover));
+
+    {
+        let palette_store = palette_store.clone();
+        let persist_palettes = persist_palettes.clone();
+        let refresh_palettes = refresh_palettes.clone();
+        palette_dropdown.connect_selected_notify(move |dropdown| {
+            if palette_updating.get() {
+                return;
+            }
+            palette_store.borrow_mut().active = dropdown.selected() as usize;
+            persist_palettes();
+            refresh_palettes();
+        });
+    }
+    {
+        // Each edit returns the swatch that should stay selected afterwards.
+        let edit_palette =
+            |button: &gtk::Button,
+             edit: fn(&mut Vec<Swatch>, Option<usize>, gdk::RGBA) -> Option<usize>| {
+                let state = state.clone();
+                let palette_store = palette_store.clone();
+                let selected_swatch = selected_swatch.clone();
+                let persist_palettes = persist_palettes.clone();
+                let refresh_palettes = refresh_palettes.clone();
+                button.connect_clicked(move |_| {
+                    let color = state.borrow().color;
+                    let selected = {
+                        let mut store = palette_store.borrow_mut();
+                        let Some(palette) = store.active_palette_mut() else {
+                            return;
+                        };
+                        let selected = selected_swatch
+                            .get()
+                            .filter(|index| *index < palette.swatches.len());
+                        edit(&mut palette.swatches, selected, color)
+                    };
+                    selected_swatch.set(selected);
+                    persist_palettes();
+                    refresh_palettes();
+                });
+            };
+        edit_palette(&add_swatch_button, |swatches, _, color| {
+            swatches.push(Swatch {
+                color,
+                name: String::new(),
+            });
+            Some(swatches.len() - 1)
+        });
+        edit_palette(&remove_swatch_button, |swatches, selected, _| {
+            if let Some(index) = selected {
+                swatches.remove(index);
+            }
+            None
+        });
+        edit_palette(&move_left_button, |swatches, selected, _| {
+            let index = selected?;
+            if index == 0 {
+                return selected;
+            }
+            swatches.swap(index, index - 1);
+            Some(index - 1)
+        });
+        edit_palette(&move_right_button, |swatches, selected, _| {
+            let index = selected?;
+            if index + 1 >= swatches.len() {
+                return selected;
+            }
+            swatches.swap(index, index + 1);
+            Some(index + 1)
+        });
+    }
+    {
+        let palette_store = palette_store.clone();
+        let persist_palettes = persist_palettes.clone();
+        let refresh_palettes = refresh_palettes.clone();
+        new_palette_button.connect_clicked(move |_| {
+            {
+                let mut store = palette_store.borrow_mut();
+                let name = format!("Palette {}", store.palettes.len() + 1);
+                store.add_palette(palette::Palette {
+                    name,
+                    swatches: Vec::new(),
+                });
+            }
+            persist_palettes();
+            refresh_palettes();
+        });
+    }
+    {
+        let palette_store = palette_store.clone();
+        let persist_palettes = persist_palettes.clone();
+        let refresh_palettes = refresh_palettes.clone();
+        delete_palette_button.connect_clicked(move |_| {
+            if palette_store.borrow_mut().remove_active_palette() {
+                persist_palettes();
+                refresh_palettes();
+            }
+        });
+    }
+    let gpl_filter = gtk::FileFilter::new();
+    gpl_filter.set_name(Some("GIMP palettes"));
+    gpl_filter.add_suffix("gpl");
+    let gpl_filters = gio::ListStore::new::<gtk::FileFilter>();
+    gpl_filters.append(&gpl_filter);
+    {
+        let window = window.clone();
+        let palette_store = palette_store.clone();
+        let set_status = set_status.clone();
+        let persist_palettes = persist_palettes.clone();
+        let refresh_palettes = refresh_palettes.clone();
+        let file_dialog = gtk::FileDialog::new();
+        file_dialog.set_title("Import Palette");
+        file_dialog.set_filters(Some(&gpl_filters));
+        import_palette_button.connect_clicked(move |_| {
+            let palette_store = palette_store.clone();
+            let set_status = set_status.clone();
+            let persist_palettes = persist_palettes.clone();
+            let refresh_palettes = refresh_palettes.clone();
+            file_dialog.open(Some(&window), None::<&gio::Cancellable>, move |res| {
+                let Ok(file) = res else {
+                    return;
+                };
+                let Some(path) = file.path() else {
+                    set_status("Failed to resolve palette path.");
+                    return;
+                };
+                match palette::import_gpl(&path) {
+                    Ok(imported) => {
+                        let msg = format!("Imported palette {}.", imported.name);
+                        palette_store.borrow_mut().add_palette(imported);
+                        persist_palettes();
+                        refresh_palettes();
+                        set_status(&msg);
+                    }
+                    Err(err) => {
+                        let msg = format!("Import failed: {err}");
+                        set_status(&msg);
+                    }
+                }
+            });
+        });
+    }
+    {
+        let window = window.clone();
+        let palette_store = palette_store.clone();
+        let set_status = set_status.clone();
+        let file_dialog = gtk::FileDialog::new();
+        file_dialog.set_title("Export Palette");
+        file_dialog.set_filters(Some(&gpl_filters));
+        export_palette_button.connect_clicked(move |_| {
+            let Some(active) = palette_store.borrow().active_palette().cloned() else {
+                return;
+            };
+            file_dialog.set_initial_name(Some(&format!("{}.gpl", active.name)));
+            let set_status = set_status.clone();
+            file_dialog.save(Some(&window), None::<&gio::Cancellable>, move |res| {
+                let Ok(file) = res else {
+                    return;
+                };
+                let Some(mut path) = file.path() else {
+                    set_status("Failed to resolve palette path.");
+                    return;
+                };
+                if path.extension().is_none() {
+                    path.set_extension("gpl");
+                }
+                match palette::export_gpl(&active, &path) {
+                    Ok(()) => set_status("Exported palette."),
+                    Err(err) => {
+                        let msg = format!("Export failed: {err}");
+                        set_status(&msg);
+                    }
+                }
+            });
+        });
+    }
+
+    Palettes {
+        button: palette_button,
+        swatch_box,
+        recent_box,
+        recent_separator,
+        selected_swatch,
+        refresh: refresh_palettes,
+        remember_color,
+        error: palette_error,
+    }
+}
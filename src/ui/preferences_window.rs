@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use adw::prelude::*;
+
+use super::UpdatePreferences;
+use crate::export::ImageFormat;
+use crate::preferences::Preferences;
+
+// Rows share these with the popover controls, so both stay in sync.
+pub struct SharedControls {
+    pub delay: gtk::Adjustment,
+    pub stroke_width: gtk::Adjustment,
+    pub text_size: gtk::Adjustment,
+    pub interactive: gtk::Switch,
+    pub secure_export: gtk::Switch,
+}
+
+pub fn window(
+    parent: &adw::ApplicationWindow,
+    preferences: &Rc<RefCell<Preferences>>,
+    update_preferences: &UpdatePreferences,
+    controls: &SharedControls,
+) -> adw::PreferencesWindow {
+    let delay_row = adw::SpinRow::new(Some(&controls.delay), 0.5, 1);
+    delay_row.set_title("Capture delay");
+    delay_row.set_subtitle("Seconds to wait before taking a screenshot");
+    let interactive_row = adw::SwitchRow::builder()
+        .title("Interactive capture")
+        .subtitle("Let the screenshot portal ask what to capture")
+        .build();
+    controls
+        .interactive
+        .bind_property("active", &interactive_row, "active")
+        .bidirectional()
+        .sync_create()
+        .build();
+    let capture_group = adw::PreferencesGroup::builder().title("Capture").build();
+    capture_group.add(&delay_row);
+    capture_group.add(&interactive_row);
+
+    let stroke_row = adw::SpinRow::new(Some(&controls.stroke_width), 1.0, 0);
+    stroke_row.set_title("Stroke width");
+    let text_row = adw::SpinRow::new(Some(&controls.text_size), 1.0, 0);
+    text_row.set_title("Text size");
+    let annotation_group = adw::PreferencesGroup::builder()
+        .title("Annotations")
+        .build();
+    annotation_group.add(&stroke_row);
+    annotation_group.add(&text_row);
+
+    let fit_row = adw::SwitchRow::builder()
+        .title("Fit images to window")
+        .subtitle("Opened images start scaled to fit instead of at 100%")
+        .active(preferences.borrow().fit_to_window)
+        .build();
+    {
+        let update_preferences = update_preferences.clone();
+        fit_row.connect_active_notify(move |row| {
+            let fit = row.is_active();
+            update_preferences(&|preferences| preferences.fit_to_window = fit);
+        });
+    }
+    let view_group = adw::PreferencesGroup::builder().title("View").build();
+    view_group.add(&fit_row);
+
+    let secure_row = adw::SwitchRow::builder()
+        .title("Secure export")
+        .subtitle(
+            "Bake redactions irreversibly as noisy blocks of at least 16px and strip \
+             metadata. Gaussian blurs and smaller blocks look coarser in the saved file.",
+        )
+        .build();
+    controls
+        .secure_export
+        .bind_property("active", &secure_row, "active")
+        .bidirectional()
+        .sync_create()
+        .build();
+    let (export_format, export_quality, png_compression, flatten_color) = {
+        let preferences = preferences.borrow();
+        (
+            preferences.export_format,
+            preferences.export_quality,
+            preferences.png_compression,
+            preferences.flatten_color,
+        )
+    };
+    let format_labels: Vec<String> = ImageFormat::ALL
+        .iter()
+        .map(|format| {
+            if format.is_writable() {
+                format.label().to_string()
+            } else {
+                format!("{} (not installed)", format.label())
+            }
+        })
+        .collect();
+    let format_labels: Vec<&str> = format_labels.iter().map(String::as_str).collect();
+    let format_row = adw::ComboRow::builder()
+        .title("Default format")
+        .subtitle("Used when a file name has no extension")
+        .model(&gtk::StringList::new(&format_labels))
+        .selected(
+            ImageFormat::ALL
+                .iter()
+                .position(|format| *format == export_format)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    {
+        let update_preferences = update_preferences.clone();
+        format_row.connect_selected_notify(move |row| {
+            if let Some(format) = ImageFormat::ALL.get(row.selected() as usize).copied() {
+                update_preferences(&|preferences| preferences.export_format = format);
+            }
+        });
+    }
+    let quality_adjustment =
+        gtk::Adjustment::new(export_quality as f64, 1.0, 100.0, 1.0, 10.0, 0.0);
+    let quality_row = adw::SpinRow::new(Some(&quality_adjustment), 1.0, 0);
+    quality_row.set_title("Quality");
+    quality_row.set_subtitle("JPEG, WebP and AVIF; lower makes smaller files");
+    {
+        let update_preferences = update_preferences.clone();
+        quality_adjustment.connect_value_changed(move |adjustment| {
+            let quality = adjustment.value() as u32;
+            update_preferences(&|preferences| preferences.export_quality = quality);
+        });
+    }
+    let compression_adjustment =
+        gtk::Adjustment::new(png_compression as f64, 0.0, 9.0, 1.0, 1.0, 0.0);
+    let compression_row = adw::SpinRow::new(Some(&compression_adjustment), 1.0, 0);
+    compression_row.set_title("PNG compression");
+    compression_row.set_subtitle("Higher is smaller but slower to save");
+    {
+        let update_preferences = update_preferences.clone();
+        compression_adjustment.connect_value_changed(move |adjustment| {
+            let compression = adjustment.value() as u32;
+            update_preferences(&|preferences| preferences.png_compression = compression);
+        });
+    }
+    let flatten_button = gtk::ColorDialogButton::builder()
+        .dialog(
+            &gtk::ColorDialog::builder()
+                .title("Background Color")
+                .with_alpha(false)
+                .build(),
+        )
+        .rgba(&flatten_color)
+        .valign(gtk::Align::Center)
+        .build();
+    {
+        let update_preferences = update_preferences.clone();
+        flatten_button.connect_rgba_notify(move |button| {
+            let color = button.rgba();
+            update_preferences(&|preferences| preferences.flatten_color = color);
+        });
+    }
+    let flatten_row = adw::ActionRow::builder()
+        .title("Background")
+        .subtitle("Fills transparent areas in JPEG and BMP files")
+        .build();
+    flatten_row.add_suffix(&flatten_button);
+    flatten_row.set_activatable_widget(Some(&flatten_button));
+
+    let export_group = adw::PreferencesGroup::builder().title("Export").build();
+    export_group.add(&secure_row);
+    export_group.add(&format_row);
+    export_group.add(&quality_row);
+    export_group.add(&compression_row);
+    export_group.add(&flatten_row);
+
+    let page = adw::PreferencesPage::new();
+    page.add(&capture_group);
+    page.add(&annotation_group);
+    page.add(&view_group);
+    page.add(&export_group);
+    let dialog = adw::PreferencesWindow::builder()
+        .transient_for(parent)
+        .modal(true)
+        .search_enabled(false)
+        .build();
+    dialog.add(&page);
+    dialog
+}
@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use adw::prelude::*;
+use gtk::gdk;
+use gtk::glib;
+
+use crate::editor::{self, Annotation, EditorState, TextEdit};
+
+// Text and callout labels are typed into a TextView laid over the canvas at the label's position.
+#[derive(Clone)]
+pub struct TextEditor {
+    state: Rc<RefCell<EditorState>>,
+    drawing_area: gtk::DrawingArea,
+    view: gtk::TextView,
+    css: gtk::CssProvider,
+}
+
+impl TextEditor {
+    pub fn new(state: &Rc<RefCell<EditorState>>, drawing_area: &gtk::DrawingArea) -> Self {
+        let view = gtk::TextView::builder()
+            .halign(gtk::Align::Start)
+            .valign(gtk::Align::Start)
+            .accepts_tab(false)
+            .width_request(40)
+            .visible(false)
+            .build();
+        view.add_css_class("text-editor");
+        let css = gtk::CssProvider::new();
+        if let Some(display) = gdk::Display::default() {
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &css,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+        }
+        let editor = TextEditor {
+            state: state.clone(),
+            drawing_area: drawing_area.clone(),
+            view,
+            css,
+        };
+
+        let keys = gtk::EventControllerKey::new();
+        {
+            let editor = editor.clone();
+            keys.connect_key_pressed(move |_, key, _, _| {
+                if key == gdk::Key::Escape {
+                    editor.commit();
+                    glib::Propagation::Stop
+                } else {
+                    glib::Propagation::Proceed
+                }
+            });
+        }
+        editor.view.add_controller(keys);
+
+        let focus = gtk::EventControllerFocus::new();
+        {
+            let editor = editor.clone();
+            focus.connect_leave(move |_| editor.commit());
+        }
+        editor.view.add_controller(focus);
+
+        editor
+    }
+
+    pub fn view(&self) -> &gtk::TextView {
+        &self.view
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.view.is_visible()
+    }
+
+    pub fn begin(&self, edit: TextEdit) {
+        let (text, pos, color, background, size, font, (scale, offset_x, offset_y)) = {
+            let mut state = self.state.borrow_mut();
+            let (text, pos, color, background, size, font) =
+                match edit.index.and_then(|index| state.annotations.get(index)) {
+                    Some(Annotation::Text {
+                        pos,
+                        text,
+                        color,
+                        size,
+                        font,
+                    }) => (text.clone(), *pos, *color, None, *size, font.clone()),
+                    Some(Annotation::Callout {
+                        pos,
+                        text,
+                        color,
+                        size,
+                        font,
+                        ..
+                    }) => (
+                        text.clone(),
+                        *pos,
+                        editor::contrast_color(color),
+                        Some(*color),
+                        *size,
+                        font.clone(),
+                    ),
+                    _ => {
+                        let background = edit.tip.map(|_| state.color);
+                        let color = match background {
+                            Some(background) => editor::contrast_color(&background),
+                            None => state.color,
+                        };
+                        (
+                            String::new(),
+                            edit.pos,
+                            color,
+                            background,
+                            state.text_size,
+                            state.text_font.clone(),
+                        )
+                    }
+                };
+            state.text_edit = Some(TextEdit { pos, ..edit });
+            state.selected = None;
+            state.selected_original = None;
+            (
+                text,
+                pos,
+                color,
+                background,
+                size,
+                font,
+                editor::view_transform(&state),
+            )
+        };
+        let color = color.to_str();
+        let background = background.map_or_else(
+            || "transparent".to_string(),
+            |color| color.to_str().to_string(),
+        );
+        self.css.load_from_string(&format!(
+            ".text-editor, .text-editor text {{ background: {background}; }}
+             .text-editor {{ font-family: \"{}\"; font-weight: {}; font-style: {}; font-size: {:.1}px; color: {color}; caret-color: {color}; box-shadow: 0 0 0 1px rgba(127,127,127,0.6); }}",
+            font.family.replace('"', ""),
+            font.weight,
+            font.css_style(),
+            size * scale,
+        ));
+        let top = editor::text_bounds(pos, &text, size, &font).y1;
+        self.view.buffer().set_text(&text);
+        self.view
+            .set_margin_start((offset_x + pos.x * scale).round().max(0.0) as i32);
+        self.view
+            .set_margin_top((offset_y + top * scale).round().max(0.0) as i32);
+        self.view.set_visible(true);
+        self.view.grab_focus();
+        self.drawing_area.queue_draw();
+    }
+
+    pub fn commit(&self) {
+        if !self.is_active() {
+            return;
+        }
+        let buffer = self.view.buffer();
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        self.view.set_visible(false);
+        self.state.borrow_mut().commit_text(&text);
+        self.drawing_area.queue_draw();
+    }
+}